@@ -5,13 +5,17 @@ use std::hash::{Hash, Hasher};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 
-use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine as _};
+use base64::{
+    engine::general_purpose::{STANDARD, STANDARD_NO_PAD},
+    Engine as _,
+};
 use highway::PortableHash;
 use rand::distr::{Alphanumeric, SampleString};
 use regex::Regex;
 
 const DEFAULT_PYTHON_VERSION: &str = "3.14";
-const KNOWN_DISTRIBUTION_FORMATS: &[&str] = &["tar|bzip2", "tar|gzip", "tar|zstd", "zip"];
+const KNOWN_DISTRIBUTION_FORMATS: &[&str] =
+    &["tar", "tar|bzip2", "tar|gzip", "tar|xz", "tar|zstd", "zip"];
 const DEFAULT_CPYTHON_SOURCE: &str =
     "https://github.com/astral-sh/python-build-standalone/releases/download/";
 const DEFAULT_PYPY_SOURCE: &str = "https://downloads.python.org/pypy/";
@@ -427,6 +431,13 @@ fn check_environment_variable(name: &str) -> String {
 }
 
 fn filename_from_url(url: &str) -> String {
+    let path = Path::new(url);
+    if path.is_absolute() {
+        if let Some(file_name) = path.file_name() {
+            return file_name.to_string_lossy().into();
+        }
+    }
+
     let parsed =
         reqwest::Url::parse(url).unwrap_or_else(|_| panic!("unable to parse URL: {}", &url));
 
@@ -764,6 +775,9 @@ fn set_distribution() {
     set_python_path(&distribution_source);
     set_site_packages_path(&distribution_source);
     set_distribution_pip_available(&distribution_source);
+    set_distribution_sha256();
+    set_distribution_mirrors();
+    set_distribution_embed_cache();
 
     let python_isolation_flag = if get_python_version() == "pypy2.7" {
         // https://docs.python.org/2/using/cmdline.html#cmdoption-e
@@ -776,6 +790,15 @@ fn set_distribution() {
     set_runtime_variable("PYAPP__PYTHON_ISOLATION_FLAG", python_isolation_flag);
 }
 
+fn set_distribution_embed_cache() {
+    let variable = "PYAPP_DISTRIBUTION_EMBED_CACHE";
+    if is_enabled(variable) {
+        set_runtime_variable(variable, "1");
+    } else {
+        set_runtime_variable(variable, "0");
+    }
+}
+
 fn set_distribution_format(distribution_source: &String) {
     let variable = "PYAPP_DISTRIBUTION_FORMAT";
     let distribution_format = env::var(variable).unwrap_or_default();
@@ -789,10 +812,14 @@ fn set_distribution_format(distribution_source: &String) {
         set_runtime_variable(variable, "tar|bzip2");
     } else if distribution_source.ends_with(".tar.gz") || distribution_source.ends_with(".tgz") {
         set_runtime_variable(variable, "tar|gzip");
+    } else if distribution_source.ends_with(".tar.xz") || distribution_source.ends_with(".txz") {
+        set_runtime_variable(variable, "tar|xz");
     } else if distribution_source.ends_with(".tar.zst")
         || distribution_source.ends_with(".tar.zstd")
     {
         set_runtime_variable(variable, "tar|zstd");
+    } else if distribution_source.ends_with(".tar") {
+        set_runtime_variable(variable, "tar");
     } else if distribution_source.ends_with(".zip") {
         set_runtime_variable(variable, "zip");
     } else {
@@ -964,6 +991,27 @@ fn set_distribution_pip_available(distribution_source: &str) {
     }
 }
 
+fn set_distribution_sha256() {
+    let variable = "PYAPP_DISTRIBUTION_SHA256";
+    let digest = env::var(variable).unwrap_or_default().to_lowercase();
+    let is_valid = digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit());
+    if !digest.is_empty() && !is_valid {
+        panic!("\n\nInvalid SHA-256 digest for {variable}: {digest}\n\n");
+    }
+    set_runtime_variable(variable, &digest);
+}
+
+fn set_distribution_mirrors() {
+    let variable = "PYAPP_DISTRIBUTION_MIRRORS";
+    let mirrors = env::var(variable).unwrap_or_default();
+    for mirror in mirrors.split(',').filter(|s| !s.is_empty()) {
+        if !Path::new(mirror).is_absolute() && reqwest::Url::parse(mirror).is_err() {
+            panic!("\n\nInvalid distribution mirror for {variable}: {mirror}\n\n");
+        }
+    }
+    set_runtime_variable(variable, &mirrors);
+}
+
 fn set_execution_mode() {
     let module_variable = "PYAPP_EXEC_MODULE";
     let module = env::var(module_variable).unwrap_or_default();
@@ -1180,6 +1228,68 @@ fn set_uv_source() {
     set_runtime_variable(source_variable, &source);
 }
 
+fn set_signature_public_keys() {
+    let variable = "PYAPP_SIGNATURE_PUBLIC_KEYS";
+    let public_keys = env::var(variable).unwrap_or_default();
+    for public_key in public_keys.split(',').filter(|s| !s.is_empty()) {
+        // https://jedisct1.github.io/minisign/#public-key-format
+        match STANDARD.decode(public_key) {
+            Ok(bytes) if bytes.len() == 42 => {}
+            _ => panic!("\n\nInvalid minisign public key for {variable}: {public_key}\n\n"),
+        }
+    }
+    set_runtime_variable(variable, &public_keys);
+}
+
+fn set_network_options() {
+    for (variable, default) in [
+        ("PYAPP_NETWORK_RETRIES", "3"),
+        ("PYAPP_NETWORK_RETRY_BACKOFF", "1"),
+        ("PYAPP_NETWORK_CONNECT_TIMEOUT", "30"),
+        ("PYAPP_NETWORK_READ_TIMEOUT", "30"),
+    ] {
+        let value = env::var(variable).unwrap_or_default();
+        if value.is_empty() {
+            set_runtime_variable(variable, default);
+        } else if value.parse::<u64>().is_ok() {
+            set_runtime_variable(variable, &value);
+        } else {
+            panic!("\n\n{variable} must be a non-negative integer: {value}\n\n");
+        }
+    }
+}
+
+fn set_lock_timeout() {
+    let variable = "PYAPP_LOCK_TIMEOUT";
+    let value = env::var(variable).unwrap_or_default();
+    if value.is_empty() {
+        set_runtime_variable(variable, "0");
+    } else if value.parse::<u64>().is_ok() {
+        set_runtime_variable(variable, &value);
+    } else {
+        panic!("\n\n{variable} must be a non-negative integer: {value}\n\n");
+    }
+}
+
+fn set_extraction_options() {
+    let variable = "PYAPP_EXTRACTION_ALLOW_EXTERNAL_SYMLINKS";
+    if is_enabled(variable) {
+        set_runtime_variable(variable, "1");
+    } else {
+        set_runtime_variable(variable, "0");
+    }
+
+    let variable = "PYAPP_EXTRACTION_MAX_SIZE";
+    let value = env::var(variable).unwrap_or_default();
+    if value.is_empty() {
+        set_runtime_variable(variable, "8192");
+    } else if value.parse::<u64>().is_ok() {
+        set_runtime_variable(variable, &value);
+    } else {
+        panic!("\n\n{variable} must be a non-negative integer: {value}\n\n");
+    }
+}
+
 fn set_skip_install() {
     let variable = "PYAPP_SKIP_INSTALL";
     if is_enabled(variable) {
@@ -1303,6 +1413,10 @@ fn main() {
     set_uv_enabled();
     set_uv_only_bootstrap();
     set_uv_source();
+    set_signature_public_keys();
+    set_network_options();
+    set_extraction_options();
+    set_lock_timeout();
     set_allow_updates();
     set_indicator();
     set_self_command();
@@ -1,7 +1,9 @@
 #![allow(clippy::eq_op)]
 
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use base64::{engine::general_purpose::STANDARD_NO_PAD, Engine as _};
@@ -10,6 +12,9 @@ use once_cell::sync::OnceCell;
 
 static PLATFORM_DIRS: OnceCell<ProjectDirs> = OnceCell::new();
 static INSTALLATION_DIRECTORY: OnceCell<PathBuf> = OnceCell::new();
+static STAGING_DIRECTORY: OnceCell<PathBuf> = OnceCell::new();
+static STAGING: AtomicBool = AtomicBool::new(false);
+static VERBOSE: AtomicBool = AtomicBool::new(false);
 
 fn platform_dirs() -> &'static ProjectDirs {
     PLATFORM_DIRS
@@ -18,11 +23,26 @@ fn platform_dirs() -> &'static ProjectDirs {
 }
 
 pub fn install_dir() -> &'static PathBuf {
+    if STAGING.load(Ordering::Relaxed) {
+        return staging_dir();
+    }
+
     INSTALLATION_DIRECTORY
         .get()
         .expect("installation directory is not initialized")
 }
 
+pub fn staging_dir() -> &'static PathBuf {
+    STAGING_DIRECTORY
+        .get()
+        .expect("staging directory is not initialized")
+}
+
+/// While staging, the installation directory refers to the staging directory.
+pub fn set_staging(staging: bool) {
+    STAGING.store(staging, Ordering::Relaxed);
+}
+
 pub fn initialize() -> Result<()> {
     let platform_directories = ProjectDirs::from("", "", "pyapp")
         .with_context(|| "unable to find platform directories")?;
@@ -35,22 +55,59 @@ pub fn initialize() -> Result<()> {
         project_name().to_uppercase()
     ))
     .unwrap_or_default();
-    let installation_directory = if !install_dir_override.is_empty() {
-        PathBuf::from(install_dir_override)
+    // The staging directory's path has the same length as that of the installation directory
+    // so that references to it may be rewritten in place, even within binary files
+    let (installation_directory, staging_directory) = if !install_dir_override.is_empty() {
+        let installation_directory = PathBuf::from(install_dir_override);
+        let staging_directory = staging_sibling(&installation_directory);
+        (installation_directory, staging_directory)
     } else {
-        platform_dirs()
-            .data_local_dir()
-            .join(project_name())
-            .join(distribution_id())
-            .join(project_version())
+        let project_directory = platform_dirs().data_local_dir().join(project_name());
+        (
+            project_directory
+                .join(distribution_id())
+                .join(project_version()),
+            project_directory.join(staging_id()).join(project_version()),
+        )
     };
     INSTALLATION_DIRECTORY
         .set(installation_directory)
         .expect("could not set installation directory");
+    STAGING_DIRECTORY
+        .set(staging_directory)
+        .expect("could not set staging directory");
 
     Ok(())
 }
 
+fn staging_id() -> String {
+    // Distribution IDs only consist of digits so this can never collide with one
+    distribution_id()
+        .chars()
+        .map(|c| {
+            c.to_digit(10)
+                .map_or(c, |digit| (b'a' + digit as u8) as char)
+        })
+        .collect()
+}
+
+fn staging_sibling(installation_directory: &Path) -> PathBuf {
+    let name = installation_directory
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    let mut chars = name.chars();
+    let staging_name = match chars.next() {
+        Some(first) => {
+            let replacement = if first == '~' { "_" } else { "~" };
+            format!("{}{}", replacement.repeat(first.len_utf8()), chars.as_str())
+        }
+        None => "~".to_string(),
+    };
+
+    installation_directory.with_file_name(staging_name)
+}
+
 fn decode_option(encoded: &'static str) -> String {
     String::from_utf8(
         STANDARD_NO_PAD
@@ -60,6 +117,28 @@ fn decode_option(encoded: &'static str) -> String {
     .unwrap_or_else(|_| panic!("{} is not valid UTF-8", encoded))
 }
 
+fn runtime_option(name: &str, default: &'static str) -> u64 {
+    // Build-time values may be overridden at runtime by the environment variable of the same name
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or_else(|| default.parse().unwrap())
+}
+
+fn runtime_path(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
+
+fn optional_duration(seconds: u64) -> Option<Duration> {
+    if seconds == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(seconds))
+    }
+}
+
 pub fn embedded_distribution() -> &'static [u8] {
     // If this is empty, then the distribution will be downloaded at runtime
     include_bytes!("embed/distribution")
@@ -100,6 +179,20 @@ pub fn distribution_source() -> String {
     env!("PYAPP_DISTRIBUTION_SOURCE").into()
 }
 
+pub fn distribution_mirrors() -> Vec<String> {
+    env!("PYAPP_DISTRIBUTION_MIRRORS")
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+pub fn distribution_sources() -> Vec<String> {
+    let mut sources = distribution_mirrors();
+    sources.push(distribution_source());
+    sources
+}
+
 pub fn distribution_format() -> String {
     env!("PYAPP_DISTRIBUTION_FORMAT").into()
 }
@@ -108,6 +201,14 @@ pub fn distribution_python_path() -> String {
     env!("PYAPP_DISTRIBUTION_PYTHON_PATH").into()
 }
 
+pub fn distribution_sha256() -> String {
+    env!("PYAPP_DISTRIBUTION_SHA256").into()
+}
+
+pub fn distribution_embed_cache() -> bool {
+    env!("PYAPP_DISTRIBUTION_EMBED_CACHE") == "1"
+}
+
 pub fn distribution_pip_available() -> bool {
     env!("PYAPP_DISTRIBUTION_PIP_AVAILABLE") == "1"
 }
@@ -210,6 +311,89 @@ pub fn uv_as_installer() -> bool {
     uv_enabled() && !uv_only_bootstrap()
 }
 
+pub fn signature_public_keys() -> Vec<String> {
+    env!("PYAPP_SIGNATURE_PUBLIC_KEYS")
+        .split(',')
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+pub fn network_retries() -> u64 {
+    runtime_option("PYAPP_NETWORK_RETRIES", env!("PYAPP_NETWORK_RETRIES"))
+}
+
+pub fn network_retry_backoff() -> Duration {
+    Duration::from_secs(runtime_option(
+        "PYAPP_NETWORK_RETRY_BACKOFF",
+        env!("PYAPP_NETWORK_RETRY_BACKOFF"),
+    ))
+}
+
+pub fn network_connect_timeout() -> Option<Duration> {
+    optional_duration(runtime_option(
+        "PYAPP_NETWORK_CONNECT_TIMEOUT",
+        env!("PYAPP_NETWORK_CONNECT_TIMEOUT"),
+    ))
+}
+
+pub fn network_read_timeout() -> Option<Duration> {
+    optional_duration(runtime_option(
+        "PYAPP_NETWORK_READ_TIMEOUT",
+        env!("PYAPP_NETWORK_READ_TIMEOUT"),
+    ))
+}
+
+pub fn extraction_allow_external_symlinks() -> bool {
+    env!("PYAPP_EXTRACTION_ALLOW_EXTERNAL_SYMLINKS") == "1"
+}
+
+pub fn extraction_max_size() -> u64 {
+    // The limit is configured in mebibytes
+    env!("PYAPP_EXTRACTION_MAX_SIZE")
+        .parse::<u64>()
+        .unwrap_or_default()
+        .saturating_mul(1024 * 1024)
+}
+
+pub fn lock_timeout() -> Option<Duration> {
+    optional_duration(runtime_option(
+        "PYAPP_LOCK_TIMEOUT",
+        env!("PYAPP_LOCK_TIMEOUT"),
+    ))
+}
+
+pub fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+        || ["true", "1"].contains(&env::var("PYAPP_VERBOSE").unwrap_or_default().as_str())
+}
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+pub fn offline() -> bool {
+    ["true", "1"].contains(&env::var("PYAPP_OFFLINE").unwrap_or_default().as_str())
+}
+
+pub fn distribution_token() -> Option<String> {
+    env::var("PYAPP_DISTRIBUTION_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+pub fn ca_bundle() -> Option<PathBuf> {
+    runtime_path("PYAPP_CA_BUNDLE")
+}
+
+pub fn client_certificate() -> Option<PathBuf> {
+    runtime_path("PYAPP_CLIENT_CERT")
+}
+
+pub fn client_key() -> Option<PathBuf> {
+    runtime_path("PYAPP_CLIENT_KEY")
+}
+
 pub fn is_gui() -> bool {
     env!("PYAPP_IS_GUI") == "1"
 }
@@ -259,6 +443,10 @@ pub fn distributions_cache() -> PathBuf {
     cache_dir().join("distributions")
 }
 
+pub fn logs_dir() -> PathBuf {
+    cache_dir().join("logs").join(project_name())
+}
+
 pub fn external_pip_cache() -> PathBuf {
     cache_dir().join("pip")
 }
@@ -287,6 +475,18 @@ pub fn managed_uv() -> PathBuf {
     managed_uv_cache().join(filename)
 }
 
+pub fn installation_marker() -> PathBuf {
+    install_dir().join(".pyapp-installation")
+}
+
+pub fn installer() -> &'static str {
+    if uv_as_installer() {
+        "uv"
+    } else {
+        "pip"
+    }
+}
+
 pub fn installation_lock() -> PathBuf {
     cache_dir().join("locks").join(format!(
         "installation-{}-{}-{}",
@@ -296,6 +496,15 @@ pub fn installation_lock() -> PathBuf {
     ))
 }
 
+pub fn runtime_lock() -> PathBuf {
+    cache_dir().join("locks").join(format!(
+        "runtime-{}-{}-{}",
+        project_name(),
+        distribution_id(),
+        project_version()
+    ))
+}
+
 pub fn installer_lock(name: &str, id: &str) -> PathBuf {
     cache_dir()
         .join("locks")
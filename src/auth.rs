@@ -0,0 +1,242 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use directories::BaseDirs;
+use reqwest::blocking::RequestBuilder;
+use reqwest::Url;
+
+use crate::app;
+
+#[derive(Default)]
+struct NetrcEntry {
+    machine: Option<String>,
+    login: Option<String>,
+    password: Option<String>,
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("NETRC").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+
+    let file_name = if cfg!(windows) { "_netrc" } else { ".netrc" };
+    BaseDirs::new().map(|dirs| dirs.home_dir().join(file_name))
+}
+
+fn netrc_tokens(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let first = match chars.next() {
+            Some(c) => c,
+            None => break,
+        };
+
+        // Quoted values may contain whitespace and backslash escapes, like curl supports
+        let mut token = String::new();
+        if first == '"' {
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => break,
+                    '\\' => match chars.next() {
+                        Some('n') => token.push('\n'),
+                        Some('r') => token.push('\r'),
+                        Some('t') => token.push('\t'),
+                        Some(c) => token.push(c),
+                        None => {}
+                    },
+                    _ => token.push(c),
+                }
+            }
+        } else {
+            token.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                token.push(c);
+            }
+        }
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+fn netrc_entries(contents: &str) -> Vec<NetrcEntry> {
+    let mut entries = Vec::new();
+    let mut tokens = Vec::new();
+
+    // Macro definitions span every line until the next blank line
+    let mut in_macro = false;
+    let mut value_expected = false;
+    for line in contents.lines() {
+        if in_macro {
+            in_macro = !line.trim().is_empty();
+            continue;
+        }
+
+        for token in netrc_tokens(line) {
+            if token == "macdef" && !value_expected {
+                in_macro = true;
+                break;
+            }
+            value_expected = !value_expected
+                && matches!(token.as_str(), "machine" | "login" | "password" | "account");
+            tokens.push(token);
+        }
+    }
+
+    let mut tokens = tokens.into_iter();
+    let mut entry: Option<NetrcEntry> = None;
+    while let Some(token) = tokens.next() {
+        match token.as_str() {
+            "machine" | "default" => {
+                entries.extend(entry.take());
+                entry = Some(NetrcEntry {
+                    machine: if token == "machine" {
+                        tokens.next()
+                    } else {
+                        None
+                    },
+                    ..Default::default()
+                });
+            }
+            "login" | "password" | "account" => {
+                let value = tokens.next();
+                if let Some(entry) = entry.as_mut() {
+                    if token == "login" {
+                        entry.login = value;
+                    } else if token == "password" {
+                        entry.password = value;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    entries.extend(entry);
+
+    entries
+}
+
+fn netrc_lookup(entries: &[NetrcEntry], host: &str) -> Option<(String, String)> {
+    // The default entry only applies if no machine matches
+    let entry = entries
+        .iter()
+        .find(|entry| entry.machine.as_deref() == Some(host))
+        .or_else(|| entries.iter().find(|entry| entry.machine.is_none()))?;
+
+    Some((
+        entry.login.clone().unwrap_or_default(),
+        entry.password.clone()?,
+    ))
+}
+
+fn netrc_credentials(host: &str) -> Option<(String, String)> {
+    let contents = fs::read_to_string(netrc_path()?).ok()?;
+    netrc_lookup(&netrc_entries(&contents), host)
+}
+
+fn is_distribution_host(host: &str) -> bool {
+    app::distribution_sources()
+        .iter()
+        .filter_map(|source| Url::parse(source).ok())
+        .any(|source| source.host_str() == Some(host))
+}
+
+pub fn authenticate(request: RequestBuilder, url: &Url) -> RequestBuilder {
+    // Credentials that are part of the URL are used as-is
+    if !url.username().is_empty() {
+        return request;
+    }
+
+    let host = match url.host_str() {
+        Some(host) => host,
+        None => return request,
+    };
+
+    // Tokens are only ever sent to the hosts serving the distribution
+    if let Some(token) = app::distribution_token() {
+        if is_distribution_host(host) {
+            return request.bearer_auth(token);
+        }
+    }
+
+    if let Some((login, password)) = netrc_credentials(host) {
+        return request.basic_auth(login, Some(password));
+    }
+
+    request
+}
+
+pub fn redact(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) => {
+            url.set_password(None).ok();
+            url.set_username("").ok();
+            url.set_query(None);
+            url.to_string()
+        }
+        Err(_) => url.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(contents: &str, host: &str) -> Option<(String, String)> {
+        netrc_lookup(&netrc_entries(contents), host)
+    }
+
+    fn credentials(login: &str, password: &str) -> Option<(String, String)> {
+        Some((login.to_string(), password.to_string()))
+    }
+
+    #[test]
+    fn machine_entries() {
+        let contents =
+            "machine a.com login u1 password p1\nmachine b.com\n  login u2\n  password p2\n";
+        assert_eq!(lookup(contents, "a.com"), credentials("u1", "p1"));
+        assert_eq!(lookup(contents, "b.com"), credentials("u2", "p2"));
+        assert_eq!(lookup(contents, "c.com"), None);
+    }
+
+    #[test]
+    fn machine_takes_precedence_over_default() {
+        let contents = "default login u1 password p1\nmachine a.com login u2 password p2\n";
+        assert_eq!(lookup(contents, "a.com"), credentials("u2", "p2"));
+        assert_eq!(lookup(contents, "b.com"), credentials("u1", "p1"));
+    }
+
+    #[test]
+    fn macros_are_skipped() {
+        let contents = "macdef init\nmachine a.com login u1 password p1\n\nmachine b.com login u2 password p2\n";
+        assert_eq!(lookup(contents, "a.com"), None);
+        assert_eq!(lookup(contents, "b.com"), credentials("u2", "p2"));
+    }
+
+    #[test]
+    fn missing_password() {
+        assert_eq!(lookup("machine a.com login u1\n", "a.com"), None);
+        assert_eq!(
+            lookup("machine a.com password p1\n", "a.com"),
+            credentials("", "p1")
+        );
+    }
+
+    #[test]
+    fn quoted_values() {
+        let contents = r#"machine a.com login "u 1" password "p \"1\" \\ x""#;
+        assert_eq!(
+            lookup(contents, "a.com"),
+            credentials("u 1", r#"p "1" \ x"#)
+        );
+    }
+
+    #[test]
+    fn keyword_values() {
+        let contents = "machine a.com login macdef password login\n";
+        assert_eq!(lookup(contents, "a.com"), credentials("macdef", "login"));
+    }
+}
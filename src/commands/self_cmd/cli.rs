@@ -1,10 +1,16 @@
 use anyhow::Result;
 use clap::{Args, Subcommand};
 
+use crate::app;
+
 /// Manage this application
 #[derive(Args, Debug)]
 #[command()]
 pub struct Cli {
+    /// Display the output of installers as they run
+    #[arg(short, long)]
+    verbose: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -12,6 +18,7 @@ pub struct Cli {
 #[derive(Subcommand, Debug)]
 enum Commands {
     Cache(super::cache::cli::Cli),
+    Logs(super::logs::Cli),
     Metadata(super::metadata::Cli),
     Pip(super::pip::Cli),
     Python(super::python::Cli),
@@ -23,8 +30,13 @@ enum Commands {
 
 impl Cli {
     pub fn exec(self) -> Result<()> {
+        if self.verbose {
+            app::set_verbose(true);
+        }
+
         match self.command {
             Commands::Cache(cli) => cli.exec(),
+            Commands::Logs(cli) => cli.exec(),
             Commands::Metadata(cli) => cli.exec(),
             Commands::Pip(cli) => cli.exec(),
             Commands::Python(cli) => cli.exec(),
@@ -0,0 +1,43 @@
+#![allow(clippy::eq_op)]
+
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+
+use crate::logs;
+
+/// List or show recent setup logs
+#[derive(Args, Debug)]
+#[command(hide = env!("PYAPP_EXPOSE_LOGS") == "0")]
+pub struct Cli {
+    /// Display the contents of a log, where 1 is the most recent
+    #[arg(short, long, value_name = "NUMBER", num_args = 0..=1, default_missing_value = "1")]
+    show: Option<usize>,
+}
+
+impl Cli {
+    pub fn exec(self) -> Result<()> {
+        let logs = logs::list()?;
+        if logs.is_empty() {
+            println!("No setup logs");
+            return Ok(());
+        }
+
+        let Some(number) = self.show else {
+            for (i, path) in logs.iter().enumerate() {
+                println!("{}: {}", i + 1, path.display());
+            }
+            return Ok(());
+        };
+
+        let Some(path) = number.checked_sub(1).and_then(|i| logs.get(i)) else {
+            bail!("log number must be between 1 and {}", logs.len());
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("unable to read setup log {}", path.display()))?;
+        print!("{}", contents);
+
+        Ok(())
+    }
+}
@@ -1,5 +1,6 @@
 pub mod cache;
 pub mod cli;
+pub mod logs;
 pub mod metadata;
 pub mod pip;
 pub mod python;
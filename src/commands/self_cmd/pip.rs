@@ -3,7 +3,25 @@
 use anyhow::{Context, Result};
 use clap::Args;
 
-use crate::{distribution, process};
+use crate::{app, distribution, fs_utils, process};
+
+// Subcommands of both pip and `uv pip` that never modify the environment
+const READ_ONLY_SUBCOMMANDS: [&str; 14] = [
+    "cache",
+    "check",
+    "completion",
+    "config",
+    "debug",
+    "download",
+    "freeze",
+    "hash",
+    "help",
+    "index",
+    "inspect",
+    "list",
+    "show",
+    "tree",
+];
 
 /// Directly invoke pip with the installed Python
 #[derive(Args, Debug)]
@@ -18,10 +36,28 @@ impl Cli {
         distribution::ensure_ready()?;
         distribution::ensure_installer_available()?;
 
+        // The lock is inherited by pip so that it is only released once pip exits
+        let _lock_file = if self.is_read_only() {
+            None
+        } else {
+            let lock_file = fs_utils::acquire_lock(&app::installation_lock())?;
+            fs_utils::make_inheritable(&lock_file)?;
+            Some(lock_file)
+        };
+
         let mut command = distribution::pip_base_command();
         command.args(self.args);
 
         process::exec(command)
             .with_context(|| "pip execution failed, consider restoring project from scratch")
     }
+
+    fn is_read_only(&self) -> bool {
+        // Global options that take values are not recognized so the lock is acquired
+        // whenever the subcommand cannot be determined
+        match self.args.iter().find(|arg| !arg.starts_with('-')) {
+            Some(subcommand) => READ_ONLY_SUBCOMMANDS.contains(&subcommand.as_str()),
+            None => true,
+        }
+    }
 }
@@ -1,9 +1,10 @@
 use std::fs;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Args;
 
-use crate::{app, terminal};
+use crate::terminal::Stage;
+use crate::{app, fs_utils, terminal};
 
 /// Remove the installation
 #[derive(Args, Debug)]
@@ -12,13 +13,21 @@ pub struct Cli {}
 
 impl Cli {
     pub fn exec(self) -> Result<()> {
+        let lock_path = app::runtime_lock();
+        let lock_file = fs_utils::try_acquire_lock(&lock_path)?.ok_or_else(|| {
+            anyhow!("unable to remove the installation while the application is running")
+        })?;
+        let installation_lock_path = app::installation_lock();
+        let installation_lock_file = fs_utils::acquire_lock(&installation_lock_path)?;
+
         if app::install_dir().is_dir() {
-            let spinner = terminal::spinner("Removing installation".to_string());
+            let spinner = terminal::spinner(Stage::Remove, "Removing installation".to_string());
             let result = fs::remove_dir_all(app::install_dir());
             spinner.finish_and_clear();
             result?;
         }
 
-        Ok(())
+        fs_utils::release_lock(&installation_lock_file, &installation_lock_path)?;
+        fs_utils::release_lock(&lock_file, &lock_path)
     }
 }
@@ -1,10 +1,11 @@
 use std::fs;
 use std::process::exit;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use clap::Args;
 
-use crate::{app, distribution, terminal};
+use crate::terminal::Stage;
+use crate::{app, distribution, fs_utils, logs, signals, terminal};
 
 /// Install the latest version
 #[derive(Args, Debug)]
@@ -26,11 +27,21 @@ impl Cli {
             exit(1);
         }
 
+        let lock_path = app::runtime_lock();
+        let lock_file = fs_utils::try_acquire_lock(&lock_path)?.ok_or_else(|| {
+            anyhow!("unable to update the installation while the application is running")
+        })?;
+        let installation_lock_path = app::installation_lock();
+        let installation_lock_file = fs_utils::acquire_lock(&installation_lock_path)?;
+
         let existing_installation = app::install_dir().is_dir();
+        if !existing_installation || self.restore {
+            signals::begin_setup(app::install_dir().clone())?;
+        }
         if !existing_installation {
             distribution::materialize()?;
         } else if self.restore {
-            let spinner = terminal::spinner("Removing installation".to_string());
+            let spinner = terminal::spinner(Stage::Remove, "Removing installation".to_string());
             let result = fs::remove_dir_all(app::install_dir());
             spinner.finish_and_clear();
             result?;
@@ -56,10 +67,16 @@ impl Cli {
             if !existing_installation {
                 fs::remove_dir_all(app::install_dir()).ok();
             }
-            println!("{}", output.trim_end());
+            logs::print_failure(&output);
+            signals::release_locks();
             exit(status.code().unwrap_or(1));
         }
 
+        distribution::write_installation_marker()?;
+        signals::end_setup();
+        fs_utils::release_lock(&installation_lock_file, &installation_lock_path)?;
+        fs_utils::release_lock(&lock_file, &lock_path)?;
+
         if !dependency_file.is_empty() {
             println!("Updated");
             return Ok(());
@@ -1,9 +1,16 @@
-use std::fs::File;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 
-use crate::terminal;
+use crate::terminal::Stage;
+use crate::{app, terminal};
+
+const MAX_EXTRACTION_THREADS: usize = 8;
 
 pub fn unpack(
     format: String,
@@ -12,62 +19,282 @@ pub fn unpack(
 ) -> Result<()> {
     let wait_message = format!("Unpacking distribution ({})", format);
     match format.as_ref() {
-        "tar|bzip2" => unpack_tar_bzip2(archive, destination, wait_message)?,
-        "tar|gzip" => unpack_tar_gzip(archive, destination, wait_message)?,
-        "tar|zstd" => unpack_tar_zstd(archive, destination, wait_message)?,
         "zip" => unpack_zip(archive, destination, wait_message)?,
+        format if supports_streaming(format) => {
+            unpack_tar(format, archive, destination, wait_message)?
+        }
         _ => bail!("unsupported distribution format: {}", format),
     }
 
     Ok(())
 }
 
-fn unpack_tar_bzip2(
-    path: impl AsRef<Path>,
-    destination: impl AsRef<Path>,
-    wait_message: String,
-) -> Result<()> {
-    let bz = bzip2::read::BzDecoder::new(File::open(path)?);
-    let mut archive = tar::Archive::new(bz);
+struct ExtractionPolicy {
+    allow_external_symlinks: bool,
+    max_size: u64,
+    total_size: AtomicU64,
+    symlinks: Mutex<Vec<(PathBuf, PathBuf)>>,
+}
 
-    let spinner = terminal::spinner(wait_message);
-    let result = archive.unpack(destination);
-    spinner.finish_and_clear();
-    result?;
+impl ExtractionPolicy {
+    fn new() -> Self {
+        Self {
+            allow_external_symlinks: app::extraction_allow_external_symlinks(),
+            max_size: app::extraction_max_size(),
+            total_size: AtomicU64::new(0),
+            symlinks: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn check_path(&self, path: &Path) -> Result<()> {
+        for component in path.components() {
+            match component {
+                Component::Normal(_) | Component::CurDir => {}
+                Component::ParentDir => {
+                    bail!(
+                        "refusing to extract archive entry outside of the destination: {}",
+                        path.display()
+                    )
+                }
+                Component::RootDir | Component::Prefix(_) => {
+                    bail!(
+                        "refusing to extract archive entry with an absolute path: {}",
+                        path.display()
+                    )
+                }
+            }
+        }
+
+        // Nothing may be extracted through a link as it could lead anywhere
+        let path = normalize(path);
+        let symlinks = self.symlinks.lock().unwrap_or_else(|err| err.into_inner());
+        if let Some((link, _)) = symlinks
+            .iter()
+            .find(|(link, _)| path != *link && path.starts_with(link))
+        {
+            bail!(
+                "refusing to extract archive entry {} through the symlink {}",
+                path.display(),
+                link.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn check_symlink(&self, path: &Path, target: &Path) -> Result<()> {
+        if !self.allow_external_symlinks && !is_enclosed_link(path, target) {
+            bail!(
+                "refusing to extract archive entry {} that links outside of the destination: {}",
+                path.display(),
+                target.display()
+            );
+        }
+
+        self.symlinks
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .push((normalize(path), target.to_path_buf()));
+
+        Ok(())
+    }
+
+    fn verify_symlinks(&self, destination: &Path) -> Result<()> {
+        if self.allow_external_symlinks {
+            return Ok(());
+        }
+
+        // The textual check cannot know where links that are resolved through other links
+        // lead, so every link is resolved again once all of them exist
+        let root = fs::canonicalize(destination)
+            .with_context(|| format!("unable to resolve {}", destination.display()))?;
+        let symlinks = self.symlinks.lock().unwrap_or_else(|err| err.into_inner());
+        for (path, target) in symlinks.iter() {
+            let parent = destination.join(path.parent().unwrap_or(Path::new("")));
+            let parent = fs::canonicalize(&parent)
+                .with_context(|| format!("unable to resolve {}", parent.display()))?;
+            if !resolve_link(parent, target).starts_with(&root) {
+                bail!(
+                    "refusing to extract archive entry {} that links outside of the destination: {}",
+                    path.display(),
+                    target.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_size(&self, path: &Path, size: u64) -> Result<()> {
+        // Entries may be extracted concurrently
+        let total_size = self
+            .total_size
+            .fetch_add(size, Ordering::Relaxed)
+            .saturating_add(size);
+        if self.max_size > 0 && total_size > self.max_size {
+            bail!(
+                "refusing to extract archive entry {} because the total unpacked size \
+                would exceed the limit of {} bytes",
+                path.display(),
+                self.max_size
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn is_enclosed_link(path: &Path, target: &Path) -> bool {
+    // Links are resolved relative to the directory containing them
+    let mut depth = path
+        .parent()
+        .map(|parent| {
+            parent
+                .components()
+                .filter(|component| matches!(component, Component::Normal(_)))
+                .count()
+        })
+        .unwrap_or_default();
+
+    for component in target.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth == 0 {
+                    return false;
+                }
+                depth -= 1;
+            }
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+
+    true
+}
+
+fn resolve_link(mut resolved: PathBuf, target: &Path) -> PathBuf {
+    for component in target.components() {
+        match component {
+            Component::Normal(name) => {
+                // Components that do not exist cannot be links so they are joined as is
+                let next = resolved.join(name);
+                resolved = fs::canonicalize(&next).unwrap_or(next);
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::RootDir | Component::Prefix(_) => resolved.push(component),
+        }
+    }
+
+    resolved
+}
+
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+pub fn unpack_bytes(format: String, data: &[u8], destination: impl AsRef<Path>) -> Result<()> {
+    let wait_message = format!("Unpacking distribution ({})", format);
+    if format == "zip" {
+        extract_zip(|| Ok(Cursor::new(data)), destination.as_ref(), wait_message)?;
+    } else {
+        let pb = terminal::io_progress_bar(Stage::Unpack, wait_message, data.len() as u64);
+        let result = unpack_stream(&format, pb.wrap_read(data), destination);
+        pb.finish_and_clear();
+        result?;
+    }
 
     Ok(())
 }
 
-pub fn unpack_tar_gzip(
+pub fn supports_streaming(format: &str) -> bool {
+    format == "tar" || format.starts_with("tar|")
+}
+
+pub fn unpack_stream(format: &str, reader: impl Read, destination: impl AsRef<Path>) -> Result<()> {
+    let mut archive: tar::Archive<Box<dyn Read>> = match format {
+        "tar" => tar::Archive::new(Box::new(reader)),
+        "tar|bzip2" => tar::Archive::new(Box::new(bzip2::read::BzDecoder::new(reader))),
+        "tar|gzip" => tar::Archive::new(Box::new(flate2::read::GzDecoder::new(reader))),
+        "tar|xz" => tar::Archive::new(Box::new(liblzma::read::XzDecoder::new(reader))),
+        "tar|zstd" => tar::Archive::new(Box::new(zstd::stream::read::Decoder::new(reader)?)),
+        _ => bail!("unsupported streaming distribution format: {}", format),
+    };
+
+    extract_tar(&mut archive, destination.as_ref())
+}
+
+fn extract_tar(archive: &mut tar::Archive<impl Read>, destination: &Path) -> Result<()> {
+    let policy = ExtractionPolicy::new();
+    fs::create_dir_all(destination)
+        .with_context(|| format!("unable to create directory {}", destination.display()))?;
+
+    // Directories are created last so that restrictive permissions do not prevent
+    // the extraction of their contents
+    let mut directories = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        policy.check_path(&path)?;
+
+        let entry_type = entry.header().entry_type();
+        if let Some(link_name) = entry.link_name()? {
+            if entry_type.is_symlink() {
+                policy.check_symlink(&path, &link_name)?;
+            } else if entry_type.is_hard_link() {
+                // Hard links refer to other entries of the archive
+                policy.check_path(&link_name)?;
+            }
+        }
+
+        policy.add_size(&path, entry.size())?;
+
+        if entry_type.is_dir() {
+            directories.push(entry);
+        } else {
+            entry
+                .unpack_in(destination)
+                .with_context(|| format!("unable to extract archive entry {}", path.display()))?;
+        }
+    }
+
+    for mut entry in directories {
+        let path = entry.path()?.into_owned();
+        entry
+            .unpack_in(destination)
+            .with_context(|| format!("unable to extract archive entry {}", path.display()))?;
+    }
+
+    policy.verify_symlinks(destination)
+}
+
+fn unpack_tar(
+    format: &str,
     path: impl AsRef<Path>,
     destination: impl AsRef<Path>,
     wait_message: String,
 ) -> Result<()> {
-    let gz = flate2::read::GzDecoder::new(File::open(path)?);
-    let mut archive = tar::Archive::new(gz);
-
-    let spinner = terminal::spinner(wait_message);
-    let result = archive.unpack(destination);
-    spinner.finish_and_clear();
+    // Progress is based on the amount of compressed data that has been consumed
+    let f = File::open(path)?;
+    let pb = terminal::io_progress_bar(Stage::Unpack, wait_message, f.metadata()?.len());
+    let result = unpack_stream(format, pb.wrap_read(f), destination);
+    pb.finish_and_clear();
     result?;
 
     Ok(())
 }
 
-fn unpack_tar_zstd(
+pub fn unpack_tar_gzip(
     path: impl AsRef<Path>,
     destination: impl AsRef<Path>,
     wait_message: String,
 ) -> Result<()> {
-    let zst = zstd::stream::read::Decoder::new(File::open(path)?)?;
-    let mut archive = tar::Archive::new(zst);
-
-    let spinner = terminal::spinner(wait_message);
-    let result = archive.unpack(destination);
-    spinner.finish_and_clear();
-    result?;
-
-    Ok(())
+    unpack_tar("tar|gzip", path, destination, wait_message)
 }
 
 pub fn unpack_zip(
@@ -75,12 +302,276 @@ pub fn unpack_zip(
     destination: impl AsRef<Path>,
     wait_message: String,
 ) -> Result<()> {
-    let mut archive = zip::ZipArchive::new(File::open(path)?)?;
+    let path = path.as_ref();
+    extract_zip(|| File::open(path), destination.as_ref(), wait_message)
+}
+
+fn extract_zip<R: Read + Seek>(
+    open: impl Fn() -> io::Result<R> + Sync,
+    destination: &Path,
+    wait_message: String,
+) -> Result<()> {
+    let policy = ExtractionPolicy::new();
+    let mut archive = zip::ZipArchive::new(open()?)?;
+
+    // Directories and symlinks are handled up front so that only files are written concurrently
+    let mut files = Vec::new();
+    let mut symlinks = Vec::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = PathBuf::from(file.name());
+        policy.check_path(&name)?;
+        let relative_path = file.enclosed_name().with_context(|| {
+            format!(
+                "refusing to extract archive entry with an unsafe path: {}",
+                name.display()
+            )
+        })?;
+        let path = destination.join(&relative_path);
+
+        if file.is_dir() {
+            fs::create_dir_all(&path)
+                .with_context(|| format!("unable to create directory {}", path.display()))?;
+            continue;
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("unable to create directory {}", parent.display()))?;
+        }
+
+        if file.is_symlink() {
+            let mut target = String::new();
+            file.read_to_string(&mut target).with_context(|| {
+                format!("unable to read archive entry {}", relative_path.display())
+            })?;
+            let target = PathBuf::from(target);
+            policy.check_symlink(&relative_path, &target)?;
+            symlinks.push((path, target));
+        } else {
+            files.push((i, relative_path, path));
+        }
+    }
+
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .clamp(1, MAX_EXTRACTION_THREADS)
+        .min(files.len().max(1));
+    let next_file = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+
+    let pb = terminal::count_progress_bar(Stage::Unpack, wait_message, files.len() as u64);
+    let result = thread::scope(|scope| {
+        let handles = (0..workers)
+            .map(|_| {
+                scope.spawn(|| -> Result<()> {
+                    // Every thread requires its own reader
+                    let mut archive = zip::ZipArchive::new(open()?)?;
+                    while !failed.load(Ordering::Relaxed) {
+                        let (index, relative_path, path) =
+                            match files.get(next_file.fetch_add(1, Ordering::Relaxed)) {
+                                Some(file) => file,
+                                None => break,
+                            };
+
+                        extract_zip_file(&mut archive, *index, relative_path, path, &policy)
+                            .inspect_err(|_| failed.store(true, Ordering::Relaxed))?;
+                        pb.inc(1);
+                    }
 
-    let spinner = terminal::spinner(wait_message);
-    let result = archive.extract(destination);
-    spinner.finish_and_clear();
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .unwrap_or_else(|_| bail!("extraction thread panicked"))
+            })
+            .collect::<Result<Vec<_>>>()
+    });
+    pb.finish_and_clear();
     result?;
 
+    // Symlinks are created last so that no entry can be written through one of them
+    for (path, target) in symlinks {
+        create_symlink(&target, &path)
+            .with_context(|| format!("unable to create symlink {}", path.display()))?;
+    }
+
+    policy.verify_symlinks(destination)
+}
+
+fn extract_zip_file(
+    archive: &mut zip::ZipArchive<impl Read + Seek>,
+    index: usize,
+    relative_path: &Path,
+    path: &Path,
+    policy: &ExtractionPolicy,
+) -> Result<()> {
+    let mut file = archive.by_index(index)?;
+    let mut f =
+        File::create(path).with_context(|| format!("unable to create {}", path.display()))?;
+
+    // The declared size cannot be trusted so the limit is enforced while writing
+    let mut buffer = [0; 64 * 1024];
+    loop {
+        let bytes_read = file.read(&mut buffer).with_context(|| {
+            format!(
+                "unable to extract archive entry {}",
+                relative_path.display()
+            )
+        })?;
+        if bytes_read == 0 {
+            break;
+        }
+        policy.add_size(relative_path, bytes_read as u64)?;
+        f.write_all(&buffer[..bytes_read])
+            .with_context(|| format!("unable to write {}", path.display()))?;
+    }
+
+    #[cfg(unix)]
+    if let Some(mode) = file.unix_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("unable to set permissions of {}", path.display()))?;
+    }
+
     Ok(())
 }
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, path: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, path)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, path: &Path) -> io::Result<()> {
+    let resolved_target = path.parent().unwrap_or(path).join(target);
+    if resolved_target.is_dir() {
+        std::os::windows::fs::symlink_dir(target, path)
+    } else {
+        std::os::windows::fs::symlink_file(target, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ExtractionPolicy {
+        ExtractionPolicy {
+            allow_external_symlinks: false,
+            max_size: 0,
+            total_size: AtomicU64::new(0),
+            symlinks: Mutex::new(Vec::new()),
+        }
+    }
+
+    #[test]
+    fn enclosed_links() {
+        assert!(is_enclosed_link(Path::new("a"), Path::new("b")));
+        assert!(is_enclosed_link(Path::new("a"), Path::new(".")));
+        assert!(is_enclosed_link(Path::new("a/b"), Path::new("..")));
+        assert!(is_enclosed_link(Path::new("a/b/c"), Path::new("../../d")));
+        assert!(is_enclosed_link(Path::new("./a/b"), Path::new("./../c")));
+    }
+
+    #[test]
+    fn external_links() {
+        assert!(!is_enclosed_link(Path::new("a"), Path::new("..")));
+        assert!(!is_enclosed_link(Path::new("a/b"), Path::new("../..")));
+        assert!(!is_enclosed_link(Path::new("a/b"), Path::new("c/../../..")));
+        assert!(!is_enclosed_link(Path::new("./a"), Path::new("./..")));
+        assert!(!is_enclosed_link(Path::new("a"), Path::new("/etc")));
+    }
+
+    #[test]
+    fn safe_paths() {
+        let policy = policy();
+        assert!(policy.check_path(Path::new("a")).is_ok());
+        assert!(policy.check_path(Path::new("./a/b")).is_ok());
+    }
+
+    #[test]
+    fn unsafe_paths() {
+        let policy = policy();
+        assert!(policy.check_path(Path::new("../a")).is_err());
+        assert!(policy.check_path(Path::new("a/../../b")).is_err());
+        assert!(policy.check_path(Path::new("/a")).is_err());
+    }
+
+    #[test]
+    fn paths_through_symlinks() {
+        let policy = policy();
+        policy
+            .check_symlink(Path::new("a"), Path::new("."))
+            .unwrap();
+        assert!(policy.check_path(Path::new("a")).is_ok());
+        assert!(policy.check_path(Path::new("ab")).is_ok());
+        assert!(policy.check_path(Path::new("a/b")).is_err());
+        assert!(policy.check_path(Path::new("./a/b/c")).is_err());
+    }
+
+    #[test]
+    fn chained_symlinks() {
+        // Each link is enclosed on its own but together they lead to the parent directory
+        let policy = policy();
+        policy
+            .check_symlink(Path::new("a"), Path::new("."))
+            .unwrap();
+        assert!(policy.check_path(Path::new("a/x")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_resolved_through_symlinks() {
+        let destination = tempfile::tempdir().unwrap();
+        let policy = policy();
+        for (path, target) in [("b", "."), ("a", "b/..")] {
+            policy.check_path(Path::new(path)).unwrap();
+            policy
+                .check_symlink(Path::new(path), Path::new(target))
+                .unwrap();
+            create_symlink(Path::new(target), &destination.path().join(path)).unwrap();
+        }
+
+        assert!(policy.verify_symlinks(destination.path()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dangling_symlinks_resolved_through_symlinks() {
+        let destination = tempfile::tempdir().unwrap();
+        let policy = policy();
+        for (path, target) in [("b", "."), ("a", "b/../c")] {
+            policy
+                .check_symlink(Path::new(path), Path::new(target))
+                .unwrap();
+            create_symlink(Path::new(target), &destination.path().join(path)).unwrap();
+        }
+
+        assert!(policy.verify_symlinks(destination.path()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn enclosed_symlinks_verified() {
+        let destination = tempfile::tempdir().unwrap();
+        fs::create_dir(destination.path().join("d")).unwrap();
+        let policy = policy();
+        for (path, target) in [("b", "d"), ("a", "b/.."), ("d/c", "../missing")] {
+            policy
+                .check_symlink(Path::new(path), Path::new(target))
+                .unwrap();
+            create_symlink(Path::new(target), &destination.path().join(path)).unwrap();
+        }
+
+        assert!(policy.verify_symlinks(destination.path()).is_ok());
+    }
+}
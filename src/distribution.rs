@@ -1,15 +1,21 @@
+use std::collections::HashMap;
 use std::env;
 use std::ffi::OsStr;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{exit, Command, ExitStatus};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{bail, Context, Result};
-use fs4::fs_std::FileExt;
+use anyhow::{anyhow, bail, Context, Error, Result};
+use sha2::{Digest, Sha256};
 use tempfile::tempdir;
 
-use crate::{app, compression, fs_utils, network, process};
+use crate::network::Streamed;
+use crate::terminal::Stage;
+use crate::{
+    app, auth, compression, fs_utils, logs, network, process, signals, signature, terminal,
+};
 
 #[cfg(windows)]
 const PATH_SEPARATOR: char = ';';
@@ -86,6 +92,10 @@ pub fn run_project() -> Result<()> {
         }
     }
 
+    // Progress events describe the setup so they must not be reported by anything the project runs
+    command.env_remove("PYAPP_PROGRESS_FD");
+    command.env_remove("PYAPP_PROGRESS_EVENTS");
+
     apply_env_vars(&mut command);
     if !app::exec_code().is_empty() {
         command.args(["-c", app::exec_code().as_str()]);
@@ -130,24 +140,174 @@ pub fn run_project() -> Result<()> {
     }
     command.args(env::args().skip(1));
 
+    // Management commands that modify the installation wait until every instance has exited
+    let runtime_lock = fs_utils::acquire_shared_lock(&app::runtime_lock())?;
+    fs_utils::make_inheritable(&runtime_lock)?;
+    fs_utils::expose_inherited_lock(&mut command, &runtime_lock);
+
     process::exec(command)
         .with_context(|| "project execution failed, consider restoring from scratch")
 }
 
 pub fn ensure_ready() -> Result<()> {
+    let result = ensure_installed();
+    match &result {
+        Ok(_) => terminal::emit_setup_result(None),
+        Err(err) => terminal::emit_setup_result(Some(&format!("{:#}", err))),
+    }
+
+    result
+}
+
+fn ensure_installed() -> Result<()> {
     let lock_path = app::installation_lock();
     let lock_file = fs_utils::acquire_lock(&lock_path)?;
 
     if !app::install_dir().is_dir() {
-        materialize()?;
+        install_staged()?;
+    } else if !installation_is_complete() {
+        let runtime_lock_path = app::runtime_lock();
+        let runtime_lock_file =
+            fs_utils::try_acquire_lock(&runtime_lock_path)?.ok_or_else(|| {
+                anyhow!("unable to repair the installation while the application is running")
+            })?;
 
-        if !app::skip_install() {
-            install_project()?;
+        terminal::status(&format!(
+            "Repairing incomplete or outdated installation at {}",
+            app::install_dir().display()
+        ));
+        fs::remove_dir_all(app::install_dir()).with_context(|| {
+            format!(
+                "unable to remove installation {}",
+                app::install_dir().display()
+            )
+        })?;
+        install_staged()?;
+        fs_utils::release_lock(&runtime_lock_file, &runtime_lock_path)?;
+    }
+
+    fs_utils::release_lock(&lock_file, &lock_path)
+}
+
+fn install_staged() -> Result<()> {
+    let staging_dir = app::staging_dir();
+
+    // Remove what is left of an interrupted installation
+    if staging_dir.exists() {
+        fs::remove_dir_all(staging_dir).with_context(|| {
+            format!(
+                "unable to remove stale staging directory {}",
+                staging_dir.display()
+            )
+        })?;
+    }
+
+    signals::begin_setup(staging_dir.clone())?;
+    app::set_staging(true);
+    let result = materialize()
+        .and_then(|_| {
+            if app::skip_install() {
+                Ok(())
+            } else {
+                install_project()
+            }
+        })
+        .and_then(|_| write_installation_marker());
+    app::set_staging(false);
+
+    let result = result
+        .and_then(|_| relocate_installation(staging_dir, app::install_dir()))
+        .inspect_err(|_| {
+            fs::remove_dir_all(staging_dir).ok();
+        });
+    signals::end_setup();
+
+    result
+}
+
+pub fn write_installation_marker() -> Result<()> {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let contents = format!(
+        "version={}\ndistribution={}\ninstaller={}\ntimestamp={}\n",
+        app::project_version(),
+        app::distribution_id(),
+        app::installer(),
+        timestamp
+    );
+
+    let marker_path = app::installation_marker();
+    fs::write(&marker_path, contents).with_context(|| {
+        format!(
+            "unable to write installation marker {}",
+            marker_path.display()
+        )
+    })
+}
+
+fn installation_is_complete() -> bool {
+    let contents = match fs::read_to_string(app::installation_marker()) {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+
+    let fields: HashMap<&str, &str> = contents
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .collect();
+
+    fields.get("version") == Some(&app::project_version().as_str())
+        && fields.get("distribution") == Some(&app::distribution_id().as_str())
+        && fields.get("installer") == Some(&app::installer())
+}
+
+fn relocate_installation(staging_dir: &Path, install_dir: &Path) -> Result<()> {
+    // Scripts and virtual environment metadata refer to the absolute path of the installation
+    let staging_path = staging_dir.to_string_lossy();
+    let install_path = install_dir.to_string_lossy();
+    let mut files = vec![staging_dir.join("pyvenv.cfg")];
+    for scripts_dir in ["bin", "Scripts"] {
+        let scripts_dir = staging_dir.join(scripts_dir);
+        if !scripts_dir.is_dir() {
+            continue;
+        }
+
+        for entry in fs::read_dir(&scripts_dir)
+            .with_context(|| format!("unable to read directory {}", scripts_dir.display()))?
+        {
+            files.push(entry?.path());
+        }
+    }
+
+    for file in files {
+        // Symlinks point to the distribution
+        if fs::symlink_metadata(&file).is_ok_and(|metadata| metadata.is_file()) {
+            fs_utils::replace_bytes(&file, staging_path.as_bytes(), install_path.as_bytes())?;
+        }
+    }
+
+    if let Some(parent) = install_dir.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("unable to create directory {}", parent.display()))?;
+    }
+    fs::rename(staging_dir, install_dir).with_context(|| {
+        format!(
+            "unable to move staging directory {} to {}",
+            staging_dir.display(),
+            install_dir.display()
+        )
+    })?;
+
+    // The default staging directory does not share a parent with the installation
+    if let Some(parent) = staging_dir.parent() {
+        if Some(parent) != install_dir.parent() {
+            fs::remove_dir(parent).ok();
         }
     }
 
-    FileExt::unlock(&lock_file)
-        .with_context(|| format!("unable to release lock file {}", lock_path.display()))
+    Ok(())
 }
 
 pub fn pip_base_command() -> Command {
@@ -172,6 +332,9 @@ pub fn pip_install_command() -> Command {
 
     if app::uv_as_installer() {
         command.arg("install");
+        if app::offline() {
+            command.arg("--offline");
+        }
     } else {
         command.args([
             "install",
@@ -181,6 +344,9 @@ pub fn pip_install_command() -> Command {
         if !app::pip_allow_config() {
             command.arg("--isolated");
         }
+        if app::offline() {
+            command.arg("--no-index");
+        }
     }
     command.args(
         app::pip_extra_args()
@@ -195,76 +361,24 @@ pub fn pip_install_command() -> Command {
 pub fn materialize() -> Result<()> {
     let distributions_dir = app::distributions_cache();
     let distribution_file = distributions_dir.join(app::distribution_id());
+    let unpacked_distribution = if app::full_isolation() {
+        app::install_dir().to_path_buf()
+    } else {
+        distributions_dir.join(format!("_{}", app::distribution_id()))
+    };
 
-    if !distribution_file.is_file() {
-        let distribution_source = app::distribution_source();
-        let distributions_dir = distribution_file.parent().unwrap();
-        fs::create_dir_all(distributions_dir).with_context(|| {
-            format!(
-                "unable to create distribution cache {}",
-                &distributions_dir.display()
-            )
-        })?;
-
-        let dir = tempdir().with_context(|| "unable to create temporary directory")?;
-        let temp_path = dir.path().join(app::distribution_id());
-
-        let mut f = fs::File::create(&temp_path).with_context(|| {
-            format!("unable to create temporary file: {}", &temp_path.display())
-        })?;
-
-        // The embedded distribution goes through the same process to become a file because
-        // the ZIP archive API requires the Seek trait for the input stream
-        if !app::embedded_distribution().is_empty() {
-            f.write(app::embedded_distribution()).with_context(|| {
-                format!(
-                    "unable to write embedded distribution to temporary file: {}",
-                    &temp_path.display()
-                )
-            })?;
-        } else {
-            network::download(&distribution_source, &mut f, "distribution")?;
-        }
-
-        fs_utils::move_temp_file(&temp_path, &distribution_file)?;
-    }
+    // The distribution cache is shared by every application
+    let lock_path = app::installer_lock("distribution", &app::distribution_id());
+    let lock_file = fs_utils::acquire_lock(&lock_path)?;
+    let result = prepare_distribution(&distribution_file, &unpacked_distribution);
+    fs_utils::release_lock(&lock_file, &lock_path)?;
+    result?;
 
     if app::full_isolation() {
-        compression::unpack(
-            app::distribution_format(),
-            &distribution_file,
-            app::install_dir(),
-        )
-        .or_else(|err| {
-            fs::remove_dir_all(app::install_dir()).ok();
-            bail!(
-                "unable to unpack to {}\n{}",
-                &app::install_dir().display(),
-                err
-            );
-        })?;
-
         if !app::skip_install() {
             ensure_base_pip(app::install_dir())?;
         }
     } else {
-        let unpacked_distribution = distributions_dir.join(format!("_{}", app::distribution_id()));
-        if !unpacked_distribution.is_dir() {
-            compression::unpack(
-                app::distribution_format(),
-                &distribution_file,
-                &unpacked_distribution,
-            )
-            .or_else(|err| {
-                fs::remove_dir_all(&unpacked_distribution).ok();
-                bail!(
-                    "unable to unpack to {}\n{}",
-                    &unpacked_distribution.display(),
-                    err
-                );
-            })?;
-        }
-
         let python_path = unpacked_distribution.join(app::distribution_python_path());
         let mut command = if app::uv_enabled() {
             ensure_uv_available()?;
@@ -273,6 +387,9 @@ pub fn materialize() -> Result<()> {
             if app::uv_only_bootstrap() {
                 command.arg("--seed");
             }
+            if app::offline() {
+                command.arg("--offline");
+            }
 
             command
         } else {
@@ -292,8 +409,14 @@ pub fn materialize() -> Result<()> {
                     "--no-warn-script-location",
                     "virtualenv",
                 ]);
-                let (status, output) =
-                    run_setup_command(upgrade_command, "Upgrading virtualenv".to_string())?;
+                if app::offline() {
+                    upgrade_command.arg("--no-index");
+                }
+                let (status, output) = run_setup_command(
+                    upgrade_command,
+                    Stage::Venv,
+                    "Upgrading virtualenv".to_string(),
+                )?;
                 check_setup_status(status, output)?;
 
                 command.args(["-m", "virtualenv"]);
@@ -311,14 +434,303 @@ pub fn materialize() -> Result<()> {
         };
 
         command.arg(app::install_dir().to_string_lossy().as_ref());
-        let (status, output) =
-            run_setup_command(command, "Creating virtual environment".to_string())?;
+        let (status, output) = run_setup_command(
+            command,
+            Stage::Venv,
+            "Creating virtual environment".to_string(),
+        )?;
         check_setup_status(status, output)?;
     }
 
     Ok(())
 }
 
+fn prepare_distribution(distribution_file: &PathBuf, unpacked_distribution: &Path) -> Result<()> {
+    // Embedded distributions are unpacked directly from memory unless a copy should be cached
+    let from_memory = !app::embedded_distribution().is_empty() && !app::distribution_embed_cache();
+
+    let mut unpacked = false;
+    if !from_memory && !distribution_file.is_file() {
+        let distributions_dir = distribution_file.parent().unwrap();
+        fs::create_dir_all(distributions_dir).with_context(|| {
+            format!(
+                "unable to create distribution cache {}",
+                &distributions_dir.display()
+            )
+        })?;
+
+        if !app::embedded_distribution().is_empty() {
+            let dir = tempdir().with_context(|| "unable to create temporary directory")?;
+            let temp_path = dir.path().join(app::distribution_id());
+
+            let mut f = fs::File::create(&temp_path).with_context(|| {
+                format!("unable to create temporary file: {}", &temp_path.display())
+            })?;
+            f.write(app::embedded_distribution()).with_context(|| {
+                format!(
+                    "unable to write embedded distribution to temporary file: {}",
+                    &temp_path.display()
+                )
+            })?;
+
+            fs_utils::move_temp_file(&temp_path, distribution_file)?;
+        } else {
+            // Partial downloads are kept in the cache so that they may be resumed
+            let partial_path = distributions_dir.join(format!("{}.part", app::distribution_id()));
+            let mut unavailable = None;
+            if can_stream_distribution(&partial_path, unpacked_distribution) {
+                match stream_distribution(&partial_path, unpacked_distribution)? {
+                    Streamed::Complete => unpacked = true,
+                    Streamed::Interrupted => {}
+                    Streamed::Unavailable(err) => unavailable = Some(err),
+                }
+            }
+            if !unpacked {
+                download_distribution(&partial_path, unavailable)?;
+            }
+
+            fs_utils::move_temp_file(&partial_path, distribution_file)?;
+            fs::remove_file(partial_source_path(&partial_path)).ok();
+            fs::remove_file(network::validator_path(&partial_path)).ok();
+        }
+    }
+
+    if !from_memory {
+        verify_distribution(distribution_file).inspect_err(|_| {
+            if unpacked {
+                fs::remove_dir_all(unpacked_distribution).ok();
+            }
+        })?;
+    }
+
+    if !unpacked && (app::full_isolation() || !unpacked_distribution.is_dir()) {
+        if from_memory {
+            verify_embedded_distribution()?;
+        }
+        let unpacking_dir = unpacking_dir(unpacked_distribution)?;
+        let result = if from_memory {
+            compression::unpack_bytes(
+                app::distribution_format(),
+                app::embedded_distribution(),
+                &unpacking_dir,
+            )
+        } else {
+            compression::unpack(
+                app::distribution_format(),
+                distribution_file,
+                &unpacking_dir,
+            )
+        };
+        result
+            .and_then(|_| move_unpacked(&unpacking_dir, unpacked_distribution))
+            .or_else(|err| {
+                fs::remove_dir_all(&unpacking_dir).ok();
+                bail!(
+                    "unable to unpack to {}\n{}",
+                    &unpacked_distribution.display(),
+                    err
+                );
+            })?;
+    }
+
+    Ok(())
+}
+
+fn can_stream_distribution(partial_path: &Path, unpacked_distribution: &Path) -> bool {
+    // Data may only be unpacked before it is fully downloaded if there is no checksum or
+    // signature to verify beforehand and there is no partial download to resume
+    compression::supports_streaming(&app::distribution_format())
+        && app::distribution_sha256().is_empty()
+        && app::signature_public_keys().is_empty()
+        && network::local_path(&app::distribution_sources()[0]).is_none()
+        && !app::offline()
+        && !partial_path.exists()
+        && !unpacked_distribution.exists()
+}
+
+fn stream_distribution(partial_path: &Path, unpacked_distribution: &Path) -> Result<Streamed> {
+    let distribution_source = &app::distribution_sources()[0];
+    let format = app::distribution_format();
+    track_partial_download(partial_path, distribution_source)?;
+    let unpacking_dir = unpacking_dir(unpacked_distribution)?;
+    let result = network::download_streaming(
+        distribution_source,
+        partial_path,
+        "distribution",
+        |reader| compression::unpack_stream(&format, reader, &unpacking_dir),
+    );
+
+    match result.and_then(|streamed| {
+        if let Streamed::Complete = streamed {
+            move_unpacked(&unpacking_dir, unpacked_distribution)?;
+        }
+        Ok(streamed)
+    }) {
+        Ok(Streamed::Complete) => Ok(Streamed::Complete),
+        Ok(streamed) => {
+            fs::remove_dir_all(&unpacking_dir).ok();
+            Ok(streamed)
+        }
+        Err(err) => {
+            fs::remove_dir_all(&unpacking_dir).ok();
+            fs::remove_file(partial_path).ok();
+            bail!(
+                "unable to unpack to {}\n{}",
+                unpacked_distribution.display(),
+                err
+            );
+        }
+    }
+}
+
+fn unpacking_dir(destination: &Path) -> Result<PathBuf> {
+    // Archives are unpacked next to their destination and only moved into place once complete
+    // so that an interrupted unpack is never mistaken for a usable distribution
+    let mut file_name = destination.file_name().unwrap().to_os_string();
+    file_name.push(".unpacking");
+    let unpacking_dir = destination.with_file_name(file_name);
+
+    if unpacking_dir.exists() {
+        fs::remove_dir_all(&unpacking_dir).with_context(|| {
+            format!(
+                "unable to remove stale directory {}",
+                unpacking_dir.display()
+            )
+        })?;
+    }
+    fs::create_dir_all(&unpacking_dir)
+        .with_context(|| format!("unable to create directory {}", unpacking_dir.display()))?;
+
+    Ok(unpacking_dir)
+}
+
+fn move_unpacked(unpacking_dir: &Path, destination: &Path) -> Result<()> {
+    if destination.exists() {
+        fs::remove_dir_all(destination)
+            .with_context(|| format!("unable to remove {}", destination.display()))?;
+    }
+
+    fs::rename(unpacking_dir, destination).with_context(|| {
+        format!(
+            "unable to move {} to {}",
+            unpacking_dir.display(),
+            destination.display()
+        )
+    })
+}
+
+fn partial_source_path(partial_path: &Path) -> PathBuf {
+    let mut file_name = partial_path.file_name().unwrap().to_os_string();
+    file_name.push(".source");
+    partial_path.with_file_name(file_name)
+}
+
+fn track_partial_download(partial_path: &Path, distribution_source: &str) -> Result<()> {
+    // Partial downloads may only be resumed from the source that they were started with
+    let source_path = partial_source_path(partial_path);
+    let source_id = format!("{:x}", Sha256::digest(distribution_source.as_bytes()));
+    if partial_path.exists()
+        && fs::read_to_string(&source_path).ok().as_deref() != Some(source_id.as_str())
+    {
+        fs::remove_file(partial_path).with_context(|| {
+            format!(
+                "unable to remove partial download: {}",
+                partial_path.display()
+            )
+        })?;
+    }
+
+    fs::write(&source_path, source_id)
+        .with_context(|| format!("unable to write {}", source_path.display()))
+}
+
+fn fetch_distribution(distribution_source: &str, partial_path: &Path) -> Result<()> {
+    track_partial_download(partial_path, distribution_source)?;
+    network::download_resumable(distribution_source, partial_path, "distribution")?;
+    signature::verify(distribution_source, partial_path, "distribution").inspect_err(|_| {
+        // Prevent untrusted data from being resumed by the next attempt
+        fs::remove_file(partial_path).ok();
+    })
+}
+
+fn download_distribution(partial_path: &Path, mut unavailable: Option<Error>) -> Result<()> {
+    // Only local sources may be used in offline mode, which also disables streaming
+    let distribution_sources = app::distribution_sources()
+        .into_iter()
+        .filter(|source| !app::offline() || network::local_path(source).is_some())
+        .collect::<Vec<_>>();
+    if distribution_sources.is_empty() {
+        return network::ensure_online("distribution");
+    }
+
+    let mut failures = Vec::new();
+    for (i, distribution_source) in distribution_sources.iter().enumerate() {
+        // Streaming already exhausted the retries of the first source
+        let result = match unavailable.take() {
+            Some(err) => Err(err),
+            None => fetch_distribution(distribution_source, partial_path),
+        };
+        match result {
+            Ok(_) => return Ok(()),
+            Err(err) if distribution_sources.len() == 1 => return Err(err),
+            Err(err) => {
+                // The errors of every source are part of the final error if none succeeds
+                if i + 1 < distribution_sources.len() {
+                    terminal::status(&format!(
+                        "Unable to download distribution from {}, trying the next source: {}",
+                        auth::redact(distribution_source),
+                        err.root_cause()
+                    ));
+                }
+                failures.push(format!("{:#}", err));
+            }
+        }
+    }
+
+    bail!(
+        "unable to download distribution from any source:\n{}",
+        failures.join("\n")
+    );
+}
+
+fn verify_distribution(distribution_file: &PathBuf) -> Result<()> {
+    let expected_digest = app::distribution_sha256();
+    if expected_digest.is_empty() {
+        return Ok(());
+    }
+
+    let digest = fs_utils::sha256_digest(distribution_file)?;
+    if digest != expected_digest {
+        fs::remove_file(distribution_file).ok();
+        bail!(
+            "distribution checksum mismatch, removed cached file {}\nexpected SHA-256: {}\nactual SHA-256: {}",
+            distribution_file.display(),
+            expected_digest,
+            digest
+        );
+    }
+
+    Ok(())
+}
+
+fn verify_embedded_distribution() -> Result<()> {
+    let expected_digest = app::distribution_sha256();
+    if expected_digest.is_empty() {
+        return Ok(());
+    }
+
+    let digest = format!("{:x}", Sha256::digest(app::embedded_distribution()));
+    if digest != expected_digest {
+        bail!(
+            "embedded distribution checksum mismatch\nexpected SHA-256: {}\nactual SHA-256: {}",
+            expected_digest,
+            digest
+        );
+    }
+
+    Ok(())
+}
+
 fn install_project() -> Result<()> {
     let install_target = format!("{} {}", app::project_name(), app::project_version());
     let binary_only = app::pip_extra_args().contains("--only-binary :all:")
@@ -376,7 +788,7 @@ fn install_project() -> Result<()> {
 
 pub fn pip_install(command: Command, wait_message: String) -> Result<(ExitStatus, String)> {
     ensure_installer_available()?;
-    run_setup_command(command, wait_message)
+    run_setup_command(command, Stage::Install, wait_message)
 }
 
 pub fn pip_install_dependency_file(
@@ -400,7 +812,7 @@ pub fn pip_install_dependency_file(
     command.args(["-r", temp_path.to_string_lossy().as_ref()]);
 
     ensure_installer_available()?;
-    run_setup_command(command, wait_message)
+    run_setup_command(command, Stage::Install, wait_message)
 }
 
 fn ensure_base_pip(distribution_directory: &Path) -> Result<()> {
@@ -411,7 +823,7 @@ fn ensure_base_pip(distribution_directory: &Path) -> Result<()> {
     let mut command = python_command(&distribution_directory.join(app::distribution_python_path()));
     command.args(["-m", "ensurepip"]);
 
-    run_setup_command(command, "Validating pip".to_string())?;
+    run_setup_command(command, Stage::Install, "Validating pip".to_string())?;
     Ok(())
 }
 
@@ -424,8 +836,7 @@ pub fn ensure_installer_available() -> Result<()> {
             app::installer_lock("pip", external_pip.file_name().unwrap().to_str().unwrap());
         let lock_file = fs_utils::acquire_lock(&lock_path)?;
         if external_pip.is_file() {
-            return FileExt::unlock(&lock_file)
-                .with_context(|| format!("unable to release lock file {}", lock_path.display()));
+            return fs_utils::release_lock(&lock_file, &lock_path);
         }
 
         let external_pip_cache = app::external_pip_cache();
@@ -439,10 +850,6 @@ pub fn ensure_installer_available() -> Result<()> {
         let dir = tempdir().with_context(|| "unable to create temporary directory")?;
         let temp_path = dir.path().join("pip.pyz");
 
-        let mut f = fs::File::create(&temp_path).with_context(|| {
-            format!("unable to create temporary file: {}", &temp_path.display())
-        })?;
-
         let pip_version = app::pip_version();
         let url = if pip_version == "latest" {
             "https://bootstrap.pypa.io/pip/pip.pyz".to_string()
@@ -453,16 +860,13 @@ pub fn ensure_installer_available() -> Result<()> {
             )
         };
 
-        network::download(
-            &url,
-            &mut f,
-            external_pip.file_name().unwrap().to_str().unwrap(),
-        )?;
+        let description = external_pip.file_name().unwrap().to_str().unwrap();
+        network::download_resumable(&url, &temp_path, description)?;
+        signature::verify(&url, &temp_path, description)?;
 
         fs_utils::move_temp_file(&temp_path, &external_pip)?;
 
-        FileExt::unlock(&lock_file)
-            .with_context(|| format!("unable to release lock file {}", lock_path.display()))?;
+        fs_utils::release_lock(&lock_file, &lock_path)?;
     }
 
     Ok(())
@@ -475,8 +879,7 @@ fn ensure_uv_available() -> Result<()> {
 
     let managed_uv = app::managed_uv();
     if managed_uv.is_file() {
-        return FileExt::unlock(&lock_file)
-            .with_context(|| format!("unable to release lock file {}", lock_path.display()));
+        return fs_utils::release_lock(&lock_file, &lock_path);
     }
 
     let managed_uv_cache = app::managed_uv_cache();
@@ -487,10 +890,8 @@ fn ensure_uv_available() -> Result<()> {
     let artifact_name = app::uv_artifact_name();
     let temp_path = dir.path().join(&artifact_name);
 
-    let mut f = fs::File::create(&temp_path)
-        .with_context(|| format!("unable to create temporary file: {}", &temp_path.display()))?;
-
-    network::download(&app::uv_source(), &mut f, "UV")?;
+    network::download_resumable(&app::uv_source(), &temp_path, "UV")?;
+    signature::verify(&app::uv_source(), &temp_path, "UV")?;
 
     if artifact_name.ends_with(".zip") {
         compression::unpack_zip(temp_path, dir.path(), "Unpacking UV".to_string())
@@ -518,18 +919,26 @@ fn ensure_uv_available() -> Result<()> {
 
     fs_utils::move_temp_file(&binary_path, &managed_uv)?;
 
-    FileExt::unlock(&lock_file)
-        .with_context(|| format!("unable to release lock file {}", lock_path.display()))
+    fs_utils::release_lock(&lock_file, &lock_path)
 }
 
-fn run_setup_command(command: Command, message: String) -> Result<(ExitStatus, String)> {
+fn run_setup_command(
+    command: Command,
+    stage: Stage,
+    message: String,
+) -> Result<(ExitStatus, String)> {
     let program = command.get_program().to_string_lossy().to_string();
-    let (status, output) = process::wait_for(command, message).with_context(|| {
-        format!(
-            "could not run program, verify distribution build metadata options: {}",
-            &program
-        )
-    })?;
+    let command_line = logs::format_command(&command);
+    let (status, output) =
+        process::wait_for(command, stage, message.clone()).with_context(|| {
+            format!(
+                "could not run program, verify distribution build metadata options: {}",
+                &program
+            )
+        })?;
+
+    // Logging is best effort, the output is displayed in full upon failure otherwise
+    logs::record(&message, &command_line, status, &output).ok();
 
     Ok((status, output))
 }
@@ -537,7 +946,8 @@ fn run_setup_command(command: Command, message: String) -> Result<(ExitStatus, S
 fn check_setup_status(status: ExitStatus, output: String) -> Result<()> {
     if !status.success() {
         fs::remove_dir_all(app::install_dir()).ok();
-        println!("{}", output.trim_end());
+        logs::print_failure(&output);
+        signals::release_locks();
         exit(status.code().unwrap_or(1));
     }
 
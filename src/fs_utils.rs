@@ -1,10 +1,22 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use fs4::fs_std::FileExt;
+use sha2::{Digest, Sha256};
 
-use crate::terminal;
+use crate::terminal::Stage;
+use crate::{app, signals, terminal};
+
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const INHERITED_LOCK_VARIABLE: &str = "PYAPP_RUNTIME_LOCK_FD";
 
 pub fn move_temp_file(temp_file: &PathBuf, destination: &PathBuf) -> Result<()> {
     if fs::rename(temp_file, destination).is_err() {
@@ -23,25 +35,383 @@ pub fn move_temp_file(temp_file: &PathBuf, destination: &PathBuf) -> Result<()>
     Ok(())
 }
 
-pub fn acquire_lock(file_path: &PathBuf) -> Result<fs::File> {
-    let locks_dir = file_path.parent().unwrap();
-    fs::create_dir_all(locks_dir)
-        .with_context(|| format!("unable to create lock directory {}", &locks_dir.display()))?;
+pub fn sha256_digest(file_path: &PathBuf) -> Result<String> {
+    let mut file = fs::File::open(file_path)
+        .with_context(|| format!("unable to open {}", file_path.display()))?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("unable to read {}", file_path.display()))?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn replace_bytes(file_path: &Path, from: &[u8], to: &[u8]) -> Result<()> {
+    let data =
+        fs::read(file_path).with_context(|| format!("unable to read {}", file_path.display()))?;
+    if from.is_empty() || !data.windows(from.len()).any(|window| window == from) {
+        return Ok(());
+    }
+
+    let mut replaced = Vec::with_capacity(data.len());
+    let mut i = 0;
+    while i < data.len() {
+        if data[i..].starts_with(from) {
+            replaced.extend_from_slice(to);
+            i += from.len();
+        } else {
+            replaced.push(data[i]);
+            i += 1;
+        }
+    }
+
+    fs::write(file_path, replaced)
+        .with_context(|| format!("unable to write {}", file_path.display()))
+}
+
+struct LockOwner {
+    pid: u32,
+    hostname: String,
+    started: u64,
+}
+
+impl LockOwner {
+    fn current() -> Self {
+        Self {
+            pid: process::id(),
+            hostname: hostname(),
+            started: unix_timestamp(),
+        }
+    }
+
+    fn read(file_path: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(file_path).ok()?;
+        let fields: HashMap<&str, &str> = contents
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .collect();
+
+        Some(Self {
+            pid: fields.get("pid")?.parse().ok()?,
+            hostname: fields.get("hostname")?.to_string(),
+            started: fields.get("started")?.parse().ok()?,
+        })
+    }
+
+    fn write(&self, mut lock_file: &fs::File) -> io::Result<()> {
+        lock_file.set_len(0)?;
+        lock_file.seek(SeekFrom::Start(0))?;
+        write!(
+            lock_file,
+            "pid={}\nhostname={}\nstarted={}\n",
+            self.pid, self.hostname, self.started
+        )
+    }
+
+    fn is_stale(&self) -> bool {
+        // Processes on other hosts cannot be inspected
+        self.hostname == hostname() && !process_exists(self.pid)
+    }
+}
+
+impl fmt::Display for LockOwner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "process {} on {} since {}s ago",
+            self.pid,
+            self.hostname,
+            unix_timestamp().saturating_sub(self.started)
+        )
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
 
-    let lock_file = fs::OpenOptions::new()
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return String::new();
+    }
+
+    let length = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).into_owned()
+}
+
+#[cfg(windows)]
+fn hostname() -> String {
+    env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    result == 0 || io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+#[cfg(windows)]
+fn process_exists(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(unix)]
+fn is_same_file(lock_file: &fs::File, file_path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (lock_file.metadata(), fs::metadata(file_path)) {
+        (Ok(opened), Ok(current)) => opened.dev() == current.dev() && opened.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(windows)]
+fn is_same_file(_lock_file: &fs::File, file_path: &Path) -> bool {
+    // Lock files cannot be removed while they are open
+    file_path.is_file()
+}
+
+fn open_lock_file(file_path: &Path) -> Result<fs::File> {
+    // The contents identify the current owner so they must not be truncated before locking
+    fs::OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        .truncate(true)
+        .truncate(false)
         .open(file_path)
-        .with_context(|| format!("unable to open lock file {}", file_path.display()))?;
+        .with_context(|| format!("unable to open lock file {}", file_path.display()))
+}
+
+fn try_lock(lock_file: &fs::File, file_path: &Path) -> Result<bool> {
+    // Contention is not an error
+    lock_file
+        .try_lock_exclusive()
+        .with_context(|| format!("unable to acquire lock file {}", file_path.display()))
+}
+
+fn describe_holder(owner: Option<&LockOwner>) -> String {
+    match owner {
+        // The process that holds the lock may not have recorded itself yet, or the record
+        // may have been left by a process that exited without clearing it
+        Some(owner) if owner.is_stale() => format!(
+            "an unknown process (the recorded owner no longer exists: {})",
+            owner
+        ),
+        Some(owner) => owner.to_string(),
+        None => "an unknown process".to_string(),
+    }
+}
+
+fn waiting_message(owner: Option<&LockOwner>) -> String {
+    format!(
+        "Waiting on shared resource held by {}",
+        describe_holder(owner)
+    )
+}
+
+fn try_lock_shared(lock_file: &fs::File, file_path: &Path) -> Result<bool> {
+    FileExt::try_lock_shared(lock_file)
+        .with_context(|| format!("unable to acquire lock file {}", file_path.display()))
+}
+
+fn wait_for_lock(
+    lock_file: &mut fs::File,
+    file_path: &Path,
+    try_lock: fn(&fs::File, &Path) -> Result<bool>,
+) -> Result<()> {
+    if try_lock(lock_file, file_path)? {
+        return Ok(());
+    }
+
+    let timeout = app::lock_timeout();
+    let start = Instant::now();
+    let spinner = terminal::spinner(
+        Stage::Lock,
+        waiting_message(LockOwner::read(file_path).as_ref()),
+    );
+    let result = loop {
+        if !is_same_file(lock_file, file_path) {
+            // The lock file was removed, e.g. manually after a timeout
+            match open_lock_file(file_path) {
+                Ok(f) => *lock_file = f,
+                Err(err) => break Err(err),
+            }
+        }
+        match try_lock(lock_file, file_path) {
+            Ok(true) => break Ok(()),
+            Ok(false) => {}
+            Err(err) => break Err(err),
+        }
 
-    if lock_file.try_lock_exclusive().is_err() {
-        let spinner = terminal::spinner("Waiting on shared resource".to_string());
-        let result = lock_file.lock_exclusive();
-        spinner.finish_and_clear();
-        result.with_context(|| format!("unable to acquire lock file {}", file_path.display()))?;
+        // A contended lock file is never removed as the lock is held by a live process, even
+        // if the recorded owner no longer exists
+        let owner = LockOwner::read(file_path);
+        if let Some(timeout) = timeout {
+            if start.elapsed() >= timeout {
+                break Err(anyhow!(
+                    "timed out after {}s waiting for lock file {} held by {}, \
+                    remove the lock file if no other instance is running",
+                    timeout.as_secs(),
+                    file_path.display(),
+                    describe_holder(owner.as_ref())
+                ));
+            }
+        }
+
+        spinner.set_message(waiting_message(owner.as_ref()));
+        thread::sleep(LOCK_POLL_INTERVAL);
+    };
+    spinner.finish_and_clear();
+
+    result
+}
+
+pub fn acquire_lock(file_path: &Path) -> Result<fs::File> {
+    let locks_dir = file_path.parent().unwrap();
+    fs::create_dir_all(locks_dir)
+        .with_context(|| format!("unable to create lock directory {}", &locks_dir.display()))?;
+
+    let mut lock_file = open_lock_file(file_path)?;
+    wait_for_lock(&mut lock_file, file_path, try_lock)?;
+
+    // Diagnostics are best effort e.g. the file system may be read-only
+    LockOwner::current().write(&lock_file).ok();
+    signals::register_lock(file_path, &lock_file, false);
+
+    Ok(lock_file)
+}
+
+/// Returns `None` if the lock is held by another process.
+pub fn try_acquire_lock(file_path: &Path) -> Result<Option<fs::File>> {
+    let locks_dir = file_path.parent().unwrap();
+    fs::create_dir_all(locks_dir)
+        .with_context(|| format!("unable to create lock directory {}", &locks_dir.display()))?;
+
+    // The application may run management commands while it holds the lock itself, in which
+    // case the lock it passed on is converted rather than contended with
+    let (lock_file, inherited) = match inherited_lock(file_path) {
+        Some(lock_file) => (lock_file, true),
+        None => (open_lock_file(file_path)?, false),
+    };
+    if !try_lock(&lock_file, file_path)? {
+        if inherited {
+            // Failed conversions may give up the previously held lock
+            try_lock_shared(&lock_file, file_path).ok();
+        }
+        return Ok(None);
     }
 
+    LockOwner::current().write(&lock_file).ok();
+    signals::register_lock(file_path, &lock_file, inherited);
+
+    Ok(Some(lock_file))
+}
+
+pub fn acquire_shared_lock(file_path: &Path) -> Result<fs::File> {
+    let locks_dir = file_path.parent().unwrap();
+    fs::create_dir_all(locks_dir)
+        .with_context(|| format!("unable to create lock directory {}", &locks_dir.display()))?;
+
+    // Only a management command holds this lock exclusively
+    let mut lock_file = open_lock_file(file_path)?;
+    wait_for_lock(&mut lock_file, file_path, try_lock_shared)?;
+
     Ok(lock_file)
 }
+
+#[cfg(unix)]
+fn inherited_lock(file_path: &Path) -> Option<fs::File> {
+    use std::mem::ManuallyDrop;
+    use std::os::unix::io::FromRawFd;
+
+    let fd: i32 = env::var(INHERITED_LOCK_VARIABLE).ok()?.parse().ok()?;
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return None;
+    }
+
+    // The descriptor must not be closed unless it refers to the lock file
+    let lock_file = ManuallyDrop::new(unsafe { fs::File::from_raw_fd(fd) });
+    if !is_same_file(&lock_file, file_path) {
+        return None;
+    }
+
+    Some(ManuallyDrop::into_inner(lock_file))
+}
+
+#[cfg(windows)]
+fn inherited_lock(_file_path: &Path) -> Option<fs::File> {
+    // The lock is held by the process that waits for the project rather than the project itself
+    None
+}
+
+#[cfg(unix)]
+fn is_inherited(lock_file: &fs::File) -> bool {
+    use std::os::unix::io::AsRawFd;
+
+    env::var(INHERITED_LOCK_VARIABLE).is_ok_and(|fd| fd == lock_file.as_raw_fd().to_string())
+}
+
+#[cfg(windows)]
+fn is_inherited(_lock_file: &fs::File) -> bool {
+    false
+}
+
+/// Passes the lock on to the project so that management commands it runs may take it over.
+#[cfg(unix)]
+pub fn expose_inherited_lock(command: &mut Command, lock_file: &fs::File) {
+    use std::os::unix::io::AsRawFd;
+
+    command.env(INHERITED_LOCK_VARIABLE, lock_file.as_raw_fd().to_string());
+}
+
+#[cfg(windows)]
+pub fn expose_inherited_lock(_command: &mut Command, _lock_file: &fs::File) {}
+
+/// Allows the lock to be held by processes that replace or are spawned by the current one.
+#[cfg(unix)]
+pub fn make_inheritable(file: &fs::File) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let result = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFD);
+        if flags == -1 {
+            flags
+        } else {
+            libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC)
+        }
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error()).with_context(|| "unable to inherit lock file");
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn make_inheritable(_file: &fs::File) -> Result<()> {
+    // The current process waits for the project to finish so the lock is held until then
+    Ok(())
+}
+
+pub fn release_lock(lock_file: &fs::File, file_path: &Path) -> Result<()> {
+    signals::unregister_lock(file_path);
+
+    // Clear the owner so that waiting processes do not consider the lock file stale
+    // once the current process exits
+    lock_file.set_len(0).ok();
+
+    // The application that passed on the lock still relies on it
+    if is_inherited(lock_file) {
+        return FileExt::lock_shared(lock_file)
+            .with_context(|| format!("unable to release lock file {}", file_path.display()));
+    }
+
+    FileExt::unlock(lock_file)
+        .with_context(|| format!("unable to release lock file {}", file_path.display()))
+}
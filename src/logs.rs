@@ -0,0 +1,168 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{self, Command, ExitStatus};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::{app, terminal};
+
+const MAX_LOGS: usize = 20;
+const SUMMARY_LINES: usize = 20;
+
+// The log of the current process is only created once a setup step runs
+static LOG: Mutex<Option<(PathBuf, File)>> = Mutex::new(None);
+
+pub fn record(
+    description: &str,
+    command_line: &str,
+    status: ExitStatus,
+    output: &str,
+) -> Result<()> {
+    let mut log = LOG.lock().unwrap_or_else(|err| err.into_inner());
+    if log.is_none() {
+        *log = Some(create()?);
+    }
+
+    let (path, file) = log.as_mut().unwrap();
+    write!(
+        file,
+        "==> {}\n$ {}\n{}\n<== {}\n\n",
+        description,
+        command_line,
+        output.trim_end(),
+        status
+    )
+    .with_context(|| format!("unable to write setup log {}", path.display()))
+}
+
+pub fn current() -> Option<PathBuf> {
+    LOG.lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()
+        .map(|(path, _)| path.clone())
+}
+
+/// Returns the setup logs of the application, most recent first.
+pub fn list() -> Result<Vec<PathBuf>> {
+    let logs_dir = app::logs_dir();
+    if !logs_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut logs = fs::read_dir(&logs_dir)
+        .with_context(|| format!("unable to read log directory {}", logs_dir.display()))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "log"))
+        .collect::<Vec<_>>();
+
+    // File names begin with a timestamp so they sort chronologically
+    logs.sort();
+    logs.reverse();
+
+    Ok(logs)
+}
+
+pub fn print_failure(output: &str) {
+    let error = match current() {
+        Some(log) => format!("setup failed, see {}", log.display()),
+        None => "setup failed".to_string(),
+    };
+    terminal::emit_setup_result(Some(&error));
+
+    let Some(log) = current() else {
+        println!("{}", output.trim_end());
+        return;
+    };
+
+    let lines = output.trim_end().lines().collect::<Vec<_>>();
+    let summary = &lines[lines.len().saturating_sub(SUMMARY_LINES)..];
+    if summary.len() < lines.len() {
+        println!(
+            "Setup failed, showing the last {} lines of output:",
+            summary.len()
+        );
+    } else {
+        println!("Setup failed with the following output:");
+    }
+    println!("{}", summary.join("\n"));
+    println!("\nThe full output has been written to {}", log.display());
+}
+
+fn create() -> Result<(PathBuf, File)> {
+    let logs_dir = app::logs_dir();
+    fs::create_dir_all(&logs_dir)
+        .with_context(|| format!("unable to create log directory {}", logs_dir.display()))?;
+    prune()?;
+
+    let path = logs_dir.join(format!(
+        "{}-{}.log",
+        format_timestamp(SystemTime::now()),
+        process::id()
+    ));
+    let mut file = File::create(&path)
+        .with_context(|| format!("unable to create setup log {}", path.display()))?;
+    write!(
+        file,
+        "Project: {} {}\nDistribution: {}\nInstallation: {}\n\n",
+        app::project_name(),
+        app::project_version(),
+        app::distribution_id(),
+        app::install_dir().display()
+    )
+    .with_context(|| format!("unable to write setup log {}", path.display()))?;
+
+    Ok((path, file))
+}
+
+fn prune() -> Result<()> {
+    // Make room for the log that is about to be created
+    for path in list()?.into_iter().skip(MAX_LOGS - 1) {
+        fs::remove_file(&path)
+            .with_context(|| format!("unable to remove setup log {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+pub fn format_command(command: &Command) -> String {
+    let mut parts = vec![command.get_program().to_string_lossy().into_owned()];
+    parts.extend(
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned()),
+    );
+    parts.join(" ")
+}
+
+fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let (days, remainder) = (seconds / 86400, seconds % 86400);
+
+    // Convert days since the epoch to a UTC calendar date
+    let z = days + 719468;
+    let era = z / 146097;
+    let day_of_era = z % 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        remainder / 3600,
+        remainder % 3600 / 60,
+        remainder % 60
+    )
+}
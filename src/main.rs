@@ -1,10 +1,14 @@
 mod app;
+mod auth;
 mod commands;
 mod compression;
 mod distribution;
 mod fs_utils;
+mod logs;
 mod network;
 mod process;
+mod signals;
+mod signature;
 mod terminal;
 
 use std::env;
@@ -15,6 +19,16 @@ use clap::Parser;
 use crate::commands::cli::Cli;
 
 fn main() -> Result<()> {
+    terminal::initialize();
+    let result = run();
+
+    // Locks are otherwise released by the operating system without clearing their owner
+    signals::release_locks();
+
+    result
+}
+
+fn run() -> Result<()> {
     app::initialize()?;
 
     if let Some(env!("PYAPP_SELF_COMMAND")) = env::args().nth(1).as_deref() {
@@ -1,23 +1,384 @@
-use std::io::Write;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
+use once_cell::sync::OnceCell;
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE};
+use reqwest::{Certificate, Identity, StatusCode, Url};
 
-use crate::terminal;
+use crate::terminal::{Progress, Stage};
+use crate::{app, auth, terminal};
 
-pub fn download(url: &String, writer: impl Write, description: &str) -> Result<()> {
-    let mut response =
-        reqwest::blocking::get(url).with_context(|| format!("download failed: {}", url))?;
+static CLIENT: OnceCell<Client> = OnceCell::new();
 
-    let pb = terminal::io_progress_bar(
-        format!("Downloading {}", description),
-        response.content_length().unwrap_or(0),
-    );
-    response.copy_to(&mut pb.wrap_write(writer))?;
-    pb.finish_and_clear();
+enum Failure {
+    Transient(Error),
+    Permanent(Error),
+}
+
+impl From<reqwest::Error> for Failure {
+    fn from(err: reqwest::Error) -> Self {
+        // URLs may contain credentials
+        let err = err.without_url();
+        if err.is_builder() || err.is_redirect() {
+            Failure::Permanent(err.into())
+        } else {
+            Failure::Transient(err.into())
+        }
+    }
+}
+
+pub enum Streamed {
+    Complete,
+    /// The transfer failed, in which case the partial download may be resumed.
+    Interrupted,
+    /// Every attempt to connect failed.
+    Unavailable(Error),
+}
+
+struct TeeReader<R, W> {
+    reader: R,
+    writer: W,
+    failed: bool,
+}
+
+impl<R: Read, W: Write> Read for TeeReader<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.reader.read(buf).inspect_err(|_| self.failed = true)?;
+        self.writer.write_all(&buf[..bytes_read])?;
+
+        Ok(bytes_read)
+    }
+}
+
+fn client() -> Result<&'static Client> {
+    CLIENT.get_or_try_init(|| {
+        // Proxies are configured by the standard environment variables e.g. HTTPS_PROXY
+        let mut builder = Client::builder()
+            .connect_timeout(app::network_connect_timeout())
+            .timeout(app::network_read_timeout());
+
+        if let Some(ca_bundle) = app::ca_bundle() {
+            let pem = fs::read(&ca_bundle)
+                .with_context(|| format!("unable to read CA bundle {}", ca_bundle.display()))?;
+            for certificate in Certificate::from_pem_bundle(&pem)
+                .with_context(|| format!("invalid CA bundle {}", ca_bundle.display()))?
+            {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+
+        if let Some(client_certificate) = app::client_certificate() {
+            let mut pem = fs::read(&client_certificate).with_context(|| {
+                format!(
+                    "unable to read client certificate {}",
+                    client_certificate.display()
+                )
+            })?;
+
+            // The private key may be stored separately from the certificate chain
+            if let Some(client_key) = app::client_key() {
+                pem.push(b'\n');
+                pem.extend(fs::read(&client_key).with_context(|| {
+                    format!("unable to read client key {}", client_key.display())
+                })?);
+            }
+
+            let identity = Identity::from_pem(&pem).with_context(|| {
+                format!(
+                    "invalid client certificate {}",
+                    client_certificate.display()
+                )
+            })?;
+            builder = builder.identity(identity);
+        }
+
+        builder
+            .build()
+            .with_context(|| "unable to initialize HTTP client")
+    })
+}
+
+pub fn ensure_online(description: &str) -> Result<()> {
+    if app::offline() {
+        bail!(
+            "unable to download missing {} because offline mode is enabled, \
+            unset the PYAPP_OFFLINE environment variable to allow network access",
+            description
+        );
+    }
+
+    Ok(())
+}
+
+fn with_retries<T>(
+    url: &str,
+    progress: &Progress,
+    mut attempt: impl FnMut() -> Result<T, Failure>,
+) -> Result<T> {
+    let retries = app::network_retries();
+    let mut backoff = app::network_retry_backoff();
+    let mut attempts = 0;
+    loop {
+        let err = match attempt() {
+            Ok(value) => return Ok(value),
+            Err(Failure::Transient(err)) if attempts < retries => err,
+            Err(Failure::Transient(err) | Failure::Permanent(err)) => {
+                return Err(err).with_context(|| format!("download failed: {}", auth::redact(url)))
+            }
+        };
+
+        attempts += 1;
+        progress.retry(attempts, retries, backoff, &format!("{:#}", err));
+        thread::sleep(backoff);
+        backoff *= 2;
+    }
+}
+
+fn get(url: &str, offset: u64, validator: Option<&str>) -> Result<Response, Failure> {
+    let parsed_url = Url::parse(url)
+        .map_err(|_| Failure::Permanent(anyhow!("invalid URL: {}", auth::redact(url))))?;
+    let request = client()
+        .map_err(Failure::Permanent)?
+        .get(parsed_url.clone());
+    let mut request = auth::authenticate(request, &parsed_url);
+    if offset > 0 {
+        request = request.header(RANGE, format!("bytes={}-", offset));
+
+        // The server sends the entire artifact instead if it changed since the partial download
+        if let Some(validator) = validator {
+            request = request.header(IF_RANGE, validator);
+        }
+    }
+
+    let response = request.send()?;
+    let status = response.status();
+    if status.is_success() || (offset > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE) {
+        Ok(response)
+    } else if status.is_server_error()
+        || status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+    {
+        Err(Failure::Transient(anyhow!("{}", status)))
+    } else {
+        Err(Failure::Permanent(anyhow!("{}", status)))
+    }
+}
+
+fn is_resumed(response: &Response, offset: u64) -> bool {
+    let expected_range = format!("bytes {}-", offset);
+    response.status() == StatusCode::PARTIAL_CONTENT
+        && response
+            .headers()
+            .get(CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with(&expected_range))
+}
+
+/// The file next to a partial download that identifies the version of the artifact.
+pub fn validator_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap().to_os_string();
+    file_name.push(".validator");
+    path.with_file_name(file_name)
+}
+
+fn record_validator(path: &Path, response: &Response) {
+    // Weak entity tags cannot be used for range requests
+    let headers = response.headers();
+    let validator = headers
+        .get(ETAG)
+        .and_then(|value| value.to_str().ok())
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| {
+            headers
+                .get(LAST_MODIFIED)
+                .and_then(|value| value.to_str().ok())
+        });
+
+    // Partial downloads without a validator are never resumed
+    let validator_path = validator_path(path);
+    match validator {
+        Some(validator) => fs::write(&validator_path, validator).ok(),
+        None => fs::remove_file(&validator_path).ok(),
+    };
+}
 
-    if response.status().is_success() {
-        Ok(())
+pub fn local_path(url: &str) -> Option<PathBuf> {
+    let path = Path::new(url);
+    if path.is_absolute() {
+        Some(path.to_path_buf())
+    } else if url.starts_with("file://") {
+        Url::parse(url).ok()?.to_file_path().ok()
     } else {
-        bail!("download failed: {}, {}", response.status(), url)
+        None
+    }
+}
+
+fn copy_local(source: &Path, path: &Path, description: &str) -> Result<()> {
+    if path.is_file() {
+        fs::remove_file(path).with_context(|| format!("unable to remove {}", path.display()))?;
+    }
+
+    // Avoid copying when the source is on the same file system
+    if fs::hard_link(source, path).is_ok() {
+        return Ok(());
+    }
+
+    let reader =
+        fs::File::open(source).with_context(|| format!("unable to open {}", source.display()))?;
+    let size = reader
+        .metadata()
+        .with_context(|| format!("unable to read {}", source.display()))?
+        .len();
+    let mut writer =
+        fs::File::create(path).with_context(|| format!("unable to create {}", path.display()))?;
+
+    let pb = terminal::io_progress_bar(Stage::Download, format!("Copying {}", description), size);
+    let result = io::copy(&mut pb.wrap_read(reader), &mut writer);
+    pb.finish_and_clear();
+    result.with_context(|| format!("unable to copy {} to {}", source.display(), path.display()))?;
+
+    Ok(())
+}
+
+fn download_progress_bar(description: &str) -> Progress {
+    terminal::io_progress_bar(Stage::Download, format!("Downloading {}", description), 0)
+}
+
+pub fn download_bytes(url: &str, description: &str) -> Result<Vec<u8>> {
+    if let Some(source) = local_path(url) {
+        return fs::read(&source).with_context(|| format!("unable to read {}", source.display()));
+    }
+
+    ensure_online(description)?;
+
+    // The step begins before connecting so that retries are reported as part of it
+    let pb = download_progress_bar(description);
+    let result = with_retries(url, &pb, || {
+        let mut response = get(url, 0, None)?;
+
+        pb.set_length(response.content_length().unwrap_or(0));
+        pb.set_position(0);
+        let mut data = Vec::new();
+        response.copy_to(&mut pb.wrap_write(&mut data))?;
+
+        Ok(data)
+    });
+    pb.finish_and_clear();
+
+    result
+}
+
+pub fn download_resumable(url: &str, path: &Path, description: &str) -> Result<()> {
+    if let Some(source) = local_path(url) {
+        return copy_local(&source, path, description);
+    }
+
+    ensure_online(description)?;
+
+    let mut f = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("unable to open partial download: {}", path.display()))?;
+
+    let pb = download_progress_bar(description);
+    let result = with_retries(url, &pb, || {
+        let mut offset = f
+            .metadata()
+            .with_context(|| format!("unable to read partial download: {}", path.display()))
+            .map_err(Failure::Permanent)?
+            .len();
+
+        let validator = fs::read_to_string(validator_path(path)).ok();
+        if offset > 0 && validator.is_none() {
+            // There is no way to tell whether the partial download belongs to the current
+            // version of the artifact
+            f.set_len(0)
+                .with_context(|| format!("unable to truncate partial download: {}", path.display()))
+                .map_err(Failure::Permanent)?;
+            offset = 0;
+        }
+
+        let mut response = get(url, offset, validator.as_deref())?;
+        if offset > 0 && !is_resumed(&response, offset) {
+            // Either the server ignored the range and is sending the entire artifact or the
+            // partial download cannot be resumed, so start over
+            f.set_len(0)
+                .with_context(|| format!("unable to truncate partial download: {}", path.display()))
+                .map_err(Failure::Permanent)?;
+            offset = 0;
+            if response.status() != StatusCode::OK {
+                response = get(url, offset, None)?;
+            }
+        }
+        if offset == 0 {
+            record_validator(path, &response);
+        }
+
+        pb.set_length(offset + response.content_length().unwrap_or(0));
+        pb.set_position(offset);
+
+        // Whatever was written is kept so the next attempt may resume from there
+        io::copy(&mut response, &mut pb.wrap_write(&mut f))
+            .map(|_| ())
+            .map_err(|err| Failure::Transient(err.into()))
+    });
+    pb.finish_and_clear();
+
+    result
+}
+
+/// Downloads to the given path while passing the data through the consumer.
+pub fn download_streaming(
+    url: &str,
+    path: &Path,
+    description: &str,
+    consume: impl FnOnce(&mut dyn Read) -> Result<()>,
+) -> Result<Streamed> {
+    ensure_online(description)?;
+
+    let pb = download_progress_bar(description);
+    let response = match with_retries(url, &pb, || get(url, 0, None)) {
+        Ok(response) => response,
+        Err(err) => {
+            pb.finish_and_clear();
+            return Ok(Streamed::Unavailable(err));
+        }
+    };
+
+    let f = fs::File::create(path)
+        .inspect_err(|_| pb.finish_and_clear())
+        .with_context(|| format!("unable to create partial download: {}", path.display()))?;
+    record_validator(path, &response);
+
+    pb.set_length(response.content_length().unwrap_or(0));
+    let mut reader = TeeReader {
+        reader: pb.wrap_read(response),
+        writer: f,
+        failed: false,
+    };
+
+    // The consumer may stop before the end of the stream e.g. archive padding
+    let result = consume(&mut reader).and_then(|_| {
+        io::copy(&mut reader, &mut io::sink())
+            .map(|_| ())
+            .with_context(|| format!("unable to write partial download: {}", path.display()))
+    });
+    pb.finish_and_clear();
+
+    match result {
+        Ok(_) => Ok(Streamed::Complete),
+        Err(err) if reader.failed => {
+            terminal::status(&format!(
+                "{:#}",
+                err.context(format!("download failed: {}", auth::redact(url)))
+            ));
+            Ok(Streamed::Interrupted)
+        }
+        Err(err) => Err(err),
     }
 }
@@ -1,29 +1,83 @@
-use std::io::Read;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader};
 #[cfg(unix)]
 use std::os::unix::process::CommandExt;
 use std::process::exit;
+#[cfg(unix)]
+use std::process::Stdio;
 use std::process::{Command, ExitStatus};
 
 use anyhow::Result;
 
-use crate::{app, terminal};
+use crate::terminal::Stage;
+use crate::{app, signals, terminal};
+
+const OUTPUT_TAIL_LINES: usize = 5;
+const OUTPUT_TAIL_WIDTH: usize = 76;
 
-pub fn wait_for(mut command: Command, message: String) -> Result<(ExitStatus, String)> {
-    let (mut reader, writer_stdout) = os_pipe::pipe()?;
+pub fn wait_for(
+    mut command: Command,
+    stage: Stage,
+    message: String,
+) -> Result<(ExitStatus, String)> {
+    let (reader, writer_stdout) = os_pipe::pipe()?;
     let writer_stderr = writer_stdout.try_clone()?;
     command.stdout(writer_stdout);
     command.stderr(writer_stderr);
 
+    // Allow the entire process tree to be terminated upon interruption. Outside of setup
+    // interruptions are not handled so the process must remain in the foreground process
+    // group in order to receive them directly.
+    #[cfg(unix)]
+    if signals::in_setup() {
+        command.process_group(0);
+
+        // Reading from the terminal would stop a background process group indefinitely,
+        // e.g. when an installer prompts for credentials
+        command.stdin(Stdio::null());
+    }
+
     let mut child = command.spawn()?;
     drop(command);
+    signals::register_child(child.id());
 
-    let spinner = terminal::spinner(message);
+    let spinner = terminal::spinner(stage, message.clone());
+    let verbose = app::verbose();
 
     let mut output = String::new();
     let result: Result<ExitStatus> = {
-        reader.read_to_string(&mut output)?;
+        let mut reader = BufReader::new(reader);
+        let mut tail = VecDeque::with_capacity(OUTPUT_TAIL_LINES);
+        let mut line = Vec::new();
+        while reader.read_until(b'\n', &mut line)? > 0 {
+            let text = String::from_utf8_lossy(&line);
+            output.push_str(&text);
+
+            let text = text.trim_end();
+            if verbose {
+                spinner.suspend(|| eprintln!("{}", text));
+            } else if terminal::events_enabled() {
+                spinner.output(text);
+            } else if !text.is_empty() {
+                // Show the most recent output so that long running commands visibly progress
+                if tail.len() == OUTPUT_TAIL_LINES {
+                    tail.pop_front();
+                }
+                tail.push_back(format!(
+                    "    {}",
+                    text.chars().take(OUTPUT_TAIL_WIDTH).collect::<String>()
+                ));
+                spinner.set_message(format!(
+                    "{}\n{}",
+                    message,
+                    tail.iter().cloned().collect::<Vec<_>>().join("\n")
+                ));
+            }
+            line.clear();
+        }
         Ok(child.wait()?)
     };
+    signals::unregister_child();
 
     spinner.finish_and_clear();
     Ok((result?, output))
@@ -44,6 +98,7 @@ pub fn exec(mut command: Command) -> Result<()> {
         exec_gui(command)
     } else {
         let status = command.status()?;
+        signals::release_locks();
         exit(status.code().unwrap_or(1));
     }
 }
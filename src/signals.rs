@@ -0,0 +1,167 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::{Mutex, MutexGuard, Once};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use fs4::fs_std::FileExt;
+
+struct State {
+    setup: bool,
+    child: Option<u32>,
+    partial_dir: Option<PathBuf>,
+    locks: Vec<(PathBuf, File, bool)>,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    setup: false,
+    child: None,
+    partial_dir: None,
+    locks: Vec::new(),
+});
+static HANDLER: Once = Once::new();
+
+fn state() -> MutexGuard<'static, State> {
+    // The state must remain accessible even if a thread panicked while holding the lock
+    STATE.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Marks the start of setup, during which interruptions remove the partially written directory.
+pub fn begin_setup(partial_dir: PathBuf) -> Result<()> {
+    install_handler()?;
+
+    let mut state = state();
+    state.setup = true;
+    state.partial_dir = Some(partial_dir);
+
+    Ok(())
+}
+
+pub fn end_setup() {
+    let mut state = state();
+    state.setup = false;
+    state.partial_dir = None;
+}
+
+/// Returns whether interruptions are currently handled, otherwise the default behavior applies.
+pub fn in_setup() -> bool {
+    state().setup
+}
+
+pub fn register_child(pid: u32) {
+    state().child = Some(pid);
+}
+
+pub fn unregister_child() {
+    state().child = None;
+}
+
+/// Inherited locks are still relied upon by the application so they are only downgraded.
+pub fn register_lock(path: &Path, lock_file: &File, inherited: bool) {
+    // The duplicated handle refers to the same lock
+    if let Ok(lock_file) = lock_file.try_clone() {
+        state()
+            .locks
+            .push((path.to_path_buf(), lock_file, inherited));
+    }
+}
+
+pub fn unregister_lock(path: &Path) {
+    state().locks.retain(|(lock_path, _, _)| lock_path != path);
+}
+
+/// Releases every lock that is still held before the process exits.
+pub fn release_locks() {
+    release_lock_files(&mut state());
+}
+
+fn release_lock_files(state: &mut State) {
+    for (_, lock_file, inherited) in state.locks.drain(..) {
+        // Waiting processes must not report a process that no longer exists as the owner
+        lock_file.set_len(0).ok();
+        if inherited {
+            FileExt::try_lock_shared(&lock_file).ok();
+        } else {
+            FileExt::unlock(&lock_file).ok();
+        }
+    }
+}
+
+#[cfg(unix)]
+fn install_handler() -> Result<()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let mut result = Ok(());
+    HANDLER.call_once(|| {
+        result = Signals::new([SIGINT, SIGTERM])
+            .with_context(|| "unable to install signal handler")
+            .map(|mut signals| {
+                thread::spawn(move || {
+                    for signal in signals.forever() {
+                        if state().setup {
+                            interrupt(128 + signal);
+                        }
+                        signal_hook::low_level::emulate_default_handler(signal).ok();
+                    }
+                });
+            });
+    });
+
+    result
+}
+
+#[cfg(windows)]
+fn install_handler() -> Result<()> {
+    let mut result = Ok(());
+    HANDLER.call_once(|| {
+        result = ctrlc::set_handler(|| {
+            // Outside of setup the console also delivers the event to the running project
+            if state().setup {
+                interrupt(130);
+            }
+        })
+        .with_context(|| "unable to install signal handler");
+    });
+
+    result
+}
+
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    // Setup commands run in their own process group so that no descendant is orphaned
+    unsafe {
+        libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+    }
+}
+
+#[cfg(windows)]
+fn kill_process_group(pid: u32) {
+    std::process::Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .output()
+        .ok();
+}
+
+fn interrupt(exit_code: i32) -> ! {
+    let mut state = state();
+    if let Some(pid) = state.child.take() {
+        kill_process_group(pid);
+    }
+
+    if let Some(partial_dir) = state.partial_dir.take() {
+        // Processes that were just killed may briefly keep files open
+        for _ in 0..10 {
+            if !partial_dir.exists() || fs::remove_dir_all(&partial_dir).is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    release_lock_files(&mut state);
+
+    exit(exit_code);
+}
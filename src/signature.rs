@@ -0,0 +1,134 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use minisign_verify::{Error as SignatureError, PublicKey, Signature};
+
+use crate::{app, auth, network};
+
+fn signature_url(url: &str) -> Result<String> {
+    if Path::new(url).is_absolute() {
+        return Ok(format!("{}.minisig", url));
+    }
+
+    let mut signature_url = reqwest::Url::parse(url)
+        .with_context(|| format!("unable to parse URL: {}", auth::redact(url)))?;
+
+    // Fragments are only used to give artifacts a file name
+    signature_url.set_fragment(None);
+    signature_url.set_path(&format!("{}.minisig", signature_url.path()));
+
+    Ok(signature_url.to_string())
+}
+
+fn artifact_names(url: &str) -> Vec<String> {
+    let path = Path::new(url);
+    if path.is_absolute() {
+        return path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .into_iter()
+            .collect();
+    }
+
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return Vec::new();
+    };
+
+    // The artifact may be named either by the last path segment or by the fragment
+    let mut names = Vec::new();
+    if let Some(name) = url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+    {
+        names.push(name.to_string());
+    }
+    if let Some(name) = url.fragment() {
+        names.push(name.to_string());
+    }
+
+    names
+}
+
+fn verify_artifact_name(signature: &Signature, url: &str, description: &str) -> Result<()> {
+    // Without this, a valid signature of one artifact would be accepted for any other
+    let signed_name = signature
+        .trusted_comment()
+        .split('\t')
+        .find_map(|field| field.strip_prefix("file:"));
+    match signed_name {
+        Some(name) if artifact_names(url).iter().any(|expected| expected == name) => Ok(()),
+        Some(name) => bail!(
+            "signature for {} was created for a different artifact: {}",
+            description,
+            name
+        ),
+        None => bail!(
+            "signature for {} does not name the signed artifact in its trusted comment",
+            description
+        ),
+    }
+}
+
+pub fn verify(url: &str, path: &Path, description: &str) -> Result<()> {
+    let public_keys = app::signature_public_keys();
+    if public_keys.is_empty() {
+        return Ok(());
+    }
+
+    let signature_url = signature_url(url)?;
+    let signature_data =
+        network::download_bytes(&signature_url, &format!("{} signature", description))?;
+
+    let signature = String::from_utf8(signature_data)
+        .ok()
+        .and_then(|data| Signature::decode(&data).ok())
+        .with_context(|| {
+            format!(
+                "invalid signature for {}: {}",
+                description,
+                auth::redact(&signature_url)
+            )
+        })?;
+
+    for public_key in public_keys {
+        let public_key = PublicKey::from_base64(&public_key)
+            .with_context(|| format!("invalid public key: {}", public_key))?;
+
+        // Only the key that created the signature can verify it
+        let mut verifier = match public_key.verify_stream(&signature) {
+            Ok(verifier) => verifier,
+            Err(SignatureError::UnexpectedKeyId) => continue,
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("unsupported signature for {}", description))
+            }
+        };
+
+        let mut file =
+            File::open(path).with_context(|| format!("unable to open {}", path.display()))?;
+        let mut buffer = [0; 64 * 1024];
+        loop {
+            let bytes_read = file
+                .read(&mut buffer)
+                .with_context(|| format!("unable to read {}", path.display()))?;
+            if bytes_read == 0 {
+                break;
+            }
+            verifier.update(&buffer[..bytes_read]);
+        }
+
+        verifier
+            .finalize()
+            .with_context(|| format!("signature verification failed for {}", description))?;
+
+        return verify_artifact_name(&signature, url, description);
+    }
+
+    bail!(
+        "signature for {} was not created by any trusted public key: {}",
+        description,
+        auth::redact(&signature_url)
+    )
+}
@@ -1,31 +1,500 @@
-use std::time::Duration;
+use std::env;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use once_cell::sync::Lazy;
 
 // https://github.com/sindresorhus/cli-spinners/blob/main/spinners.json
 const SPINNER: &[&str] = &["∙∙∙", "●∙∙", "∙●∙", "∙∙●", "∙∙∙"];
 
-pub fn io_progress_bar(message: String, size: u64) -> ProgressBar {
+const EVENT_INTERVAL: Duration = Duration::from_millis(100);
+
+static EVENTS: Lazy<Option<EventSink>> = Lazy::new(EventSink::from_env);
+static DISPLAY: Lazy<Display> = Lazy::new(Display::from_env);
+static COLOR: Lazy<bool> =
+    Lazy::new(|| env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()));
+static NEXT_TASK_ID: AtomicU64 = AtomicU64::new(1);
+
+/// The step of the setup that a progress indicator belongs to, as reported by events.
+#[derive(Clone, Copy, Debug)]
+pub enum Stage {
+    Download,
+    Unpack,
+    Venv,
+    Install,
+    Lock,
+    Remove,
+}
+
+impl Stage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Download => "download",
+            Stage::Unpack => "unpack",
+            Stage::Venv => "venv",
+            Stage::Install => "install",
+            Stage::Lock => "lock",
+            Stage::Remove => "remove",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Display {
+    /// Animated progress bars and spinners
+    Interactive,
+    /// A single line per step, for logs and piped output
+    Plain,
+    /// Nothing at all
+    Hidden,
+}
+
+impl Display {
+    fn from_env() -> Self {
+        if EVENTS.is_some()
+            || ["true", "1"].contains(&env::var("PYAPP_QUIET").unwrap_or_default().as_str())
+        {
+            Display::Hidden
+        } else if !io::stderr().is_terminal() || env::var("TERM").is_ok_and(|term| term == "dumb") {
+            Display::Plain
+        } else {
+            Display::Interactive
+        }
+    }
+}
+
+fn template(colored: &'static str, plain: &'static str) -> &'static str {
+    if *COLOR {
+        colored
+    } else {
+        plain
+    }
+}
+
+struct EventSink {
+    writer: Mutex<Box<dyn Write + Send>>,
+    started: Instant,
+}
+
+impl EventSink {
+    fn from_env() -> Option<Self> {
+        let writer: Box<dyn Write + Send> = if let Ok(value) = env::var("PYAPP_PROGRESS_FD") {
+            match value.parse().ok().and_then(file_from_descriptor) {
+                Some(file) => Box::new(file),
+                None => {
+                    eprintln!(
+                        "Ignoring PYAPP_PROGRESS_FD because {} is not an open file descriptor",
+                        value
+                    );
+                    return None;
+                }
+            }
+        } else if ["true", "1"].contains(
+            &env::var("PYAPP_PROGRESS_EVENTS")
+                .unwrap_or_default()
+                .as_str(),
+        ) {
+            Box::new(io::stderr())
+        } else {
+            return None;
+        };
+
+        Some(Self {
+            writer: Mutex::new(writer),
+            started: Instant::now(),
+        })
+    }
+
+    fn emit(&self, event: &str, fields: &[(&str, Field)]) {
+        let mut line = format!("{{\"event\":{}", json_string(event));
+        for (name, value) in fields {
+            let value = match value {
+                Field::Number(number) => number.to_string(),
+                Field::Text(text) => json_string(text),
+            };
+            line.push_str(&format!(",{}:{}", json_string(name), value));
+        }
+        line.push_str("}\n");
+
+        // The consumer may have gone away, which must not interrupt the setup
+        let mut writer = self.writer.lock().unwrap_or_else(|err| err.into_inner());
+        writer
+            .write_all(line.as_bytes())
+            .and_then(|_| writer.flush())
+            .ok();
+    }
+}
+
+enum Field<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+#[cfg(unix)]
+fn file_from_descriptor(fd: i32) -> Option<File> {
+    use std::os::unix::io::FromRawFd;
+
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return None;
+    }
+
+    // The descriptor is owned by the parent process for the lifetime of this one
+    Some(unsafe { File::from_raw_fd(fd) })
+}
+
+#[cfg(windows)]
+fn file_from_descriptor(handle: usize) -> Option<File> {
+    use std::os::windows::io::{FromRawHandle, RawHandle};
+    use windows_sys::Win32::Foundation::GetHandleInformation;
+
+    let mut flags = 0;
+    if unsafe { GetHandleInformation(handle as RawHandle, &mut flags) } == 0 {
+        return None;
+    }
+
+    // The handle is owned by the parent process for the lifetime of this one
+    Some(unsafe { File::from_raw_handle(handle as RawHandle) })
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Validates the destination of events before any file is opened that could take its place.
+pub fn initialize() {
+    Lazy::force(&EVENTS);
+}
+
+/// Displays a message about the setup unless progress output is hidden, errors are
+/// returned instead.
+pub fn status(message: &str) {
+    if *DISPLAY != Display::Hidden {
+        eprintln!("{}", message);
+    }
+}
+
+pub fn events_enabled() -> bool {
+    EVENTS.is_some()
+}
+
+/// Reports the end of the setup, successful or not, to the consumer of events.
+pub fn emit_setup_result(error: Option<&str>) {
+    if let Some(events) = EVENTS.as_ref() {
+        let elapsed = events.started.elapsed().as_millis() as u64;
+        match error {
+            None => events.emit("done", &[("elapsed_ms", Field::Number(elapsed))]),
+            Some(message) => events.emit(
+                "failed",
+                &[
+                    ("message", Field::Text(message)),
+                    ("elapsed_ms", Field::Number(elapsed)),
+                ],
+            ),
+        }
+    }
+}
+
+struct Task {
+    id: u64,
+    stage: Stage,
+    unit: Option<&'static str>,
+    started: Instant,
+    position: AtomicU64,
+    total: AtomicU64,
+    message: Mutex<String>,
+    last_report: Mutex<Instant>,
+    finished: AtomicBool,
+}
+
+impl Task {
+    fn start(stage: Stage, unit: Option<&'static str>, message: &str, total: u64) -> Option<Self> {
+        let events = EVENTS.as_ref()?;
+        let task = Self {
+            id: NEXT_TASK_ID.fetch_add(1, Ordering::Relaxed),
+            stage,
+            unit,
+            started: Instant::now(),
+            position: AtomicU64::new(0),
+            total: AtomicU64::new(total),
+            message: Mutex::new(message.to_string()),
+            last_report: Mutex::new(Instant::now()),
+            finished: AtomicBool::new(false),
+        };
+        task.emit(events, "started");
+
+        Some(task)
+    }
+
+    fn emit(&self, events: &EventSink, event: &str) {
+        let message = self.message.lock().unwrap_or_else(|err| err.into_inner());
+        let mut fields = vec![
+            ("id", Field::Number(self.id)),
+            ("stage", Field::Text(self.stage.as_str())),
+            ("message", Field::Text(&message)),
+            (
+                "elapsed_ms",
+                Field::Number(self.started.elapsed().as_millis() as u64),
+            ),
+        ];
+        if let Some(unit) = self.unit {
+            fields.push(("unit", Field::Text(unit)));
+            fields.push((
+                "position",
+                Field::Number(self.position.load(Ordering::Relaxed)),
+            ));
+            let total = self.total.load(Ordering::Relaxed);
+            if total > 0 {
+                fields.push(("total", Field::Number(total)));
+            }
+        }
+        events.emit(event, &fields);
+    }
+
+    fn report(&self, force: bool) {
+        let Some(events) = EVENTS.as_ref() else {
+            return;
+        };
+
+        // Progress is reported periodically rather than for every chunk of data
+        {
+            let mut last_report = self
+                .last_report
+                .lock()
+                .unwrap_or_else(|err| err.into_inner());
+            if !force && last_report.elapsed() < EVENT_INTERVAL {
+                return;
+            }
+            *last_report = Instant::now();
+        }
+        self.emit(events, "progress");
+    }
+
+    fn output(&self, line: &str) {
+        if let Some(events) = EVENTS.as_ref() {
+            events.emit(
+                "output",
+                &[("id", Field::Number(self.id)), ("line", Field::Text(line))],
+            );
+        }
+    }
+
+    fn retry(&self, attempt: u64, attempts: u64, delay: Duration, error: &str) {
+        if let Some(events) = EVENTS.as_ref() {
+            events.emit(
+                "retry",
+                &[
+                    ("id", Field::Number(self.id)),
+                    ("attempt", Field::Number(attempt)),
+                    ("attempts", Field::Number(attempts)),
+                    ("delay_ms", Field::Number(delay.as_millis() as u64)),
+                    ("message", Field::Text(error)),
+                ],
+            );
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(events) = EVENTS.as_ref() {
+            if !self.finished.swap(true, Ordering::Relaxed) {
+                self.emit(events, "finished");
+            }
+        }
+    }
+}
+
+/// A progress indicator that is either displayed or reported as events.
+#[derive(Clone)]
+pub struct Progress {
+    bar: ProgressBar,
+    task: Option<Arc<Task>>,
+}
+
+impl Progress {
+    fn new(bar: ProgressBar, task: Option<Task>) -> Self {
+        match *DISPLAY {
+            Display::Interactive => {}
+            Display::Plain => {
+                // Subsequent updates are not displayed as they would flood the output
+                eprintln!("{}", bar.message());
+                bar.set_draw_target(ProgressDrawTarget::hidden());
+            }
+            Display::Hidden => bar.set_draw_target(ProgressDrawTarget::hidden()),
+        }
+
+        Self {
+            bar,
+            task: task.map(Arc::new),
+        }
+    }
+
+    pub fn set_message(&self, message: String) {
+        if let Some(task) = &self.task {
+            *task.message.lock().unwrap_or_else(|err| err.into_inner()) = message.clone();
+            task.report(true);
+        }
+        self.bar.set_message(message);
+    }
+
+    pub fn set_position(&self, position: u64) {
+        if let Some(task) = &self.task {
+            task.position.store(position, Ordering::Relaxed);
+            task.report(false);
+        }
+        self.bar.set_position(position);
+    }
+
+    pub fn set_length(&self, length: u64) {
+        if let Some(task) = &self.task {
+            task.total.store(length, Ordering::Relaxed);
+        }
+        self.bar.set_length(length);
+    }
+
+    /// Reports that the step failed and will be attempted again after the delay.
+    pub fn retry(&self, attempt: u64, attempts: u64, delay: Duration, error: &str) {
+        match &self.task {
+            Some(task) => task.retry(attempt, attempts, delay, error),
+            None => {
+                // The message cannot be read while the progress indicator is suspended
+                let message = format!(
+                    "{} failed, retrying in {:?} ({}/{}): {}",
+                    self.bar.message(),
+                    delay,
+                    attempt,
+                    attempts,
+                    error
+                );
+                self.bar.suspend(|| status(&message));
+            }
+        }
+    }
+
+    pub fn inc(&self, delta: u64) {
+        if let Some(task) = &self.task {
+            task.position.fetch_add(delta, Ordering::Relaxed);
+            task.report(false);
+        }
+        self.bar.inc(delta);
+    }
+
+    /// Reports a line of output from the running command when events are enabled.
+    pub fn output(&self, line: &str) {
+        if let Some(task) = &self.task {
+            task.output(line);
+        }
+    }
+
+    pub fn suspend<F: FnOnce() -> R, R>(&self, f: F) -> R {
+        self.bar.suspend(f)
+    }
+
+    pub fn finish_and_clear(&self) {
+        if let Some(task) = &self.task {
+            task.finish();
+        }
+        self.bar.finish_and_clear();
+    }
+
+    pub fn wrap_read<R: Read>(&self, read: R) -> ProgressIo<R> {
+        ProgressIo {
+            progress: self.clone(),
+            inner: read,
+        }
+    }
+
+    pub fn wrap_write<W: Write>(&self, write: W) -> ProgressIo<W> {
+        ProgressIo {
+            progress: self.clone(),
+            inner: write,
+        }
+    }
+}
+
+/// A reader or writer that advances a progress indicator by the number of bytes transferred.
+pub struct ProgressIo<T> {
+    progress: Progress,
+    inner: T,
+}
+
+impl<R: Read> Read for ProgressIo<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let bytes_read = self.inner.read(buf)?;
+        self.progress.inc(bytes_read as u64);
+        Ok(bytes_read)
+    }
+}
+
+impl<W: Write> Write for ProgressIo<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let bytes_written = self.inner.write(buf)?;
+        self.progress.inc(bytes_written as u64);
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub fn io_progress_bar(stage: Stage, message: String, size: u64) -> Progress {
+    let task = Task::start(stage, Some("bytes"), &message, size);
     let pb = ProgressBar::new(size);
     pb.set_message(message);
     pb.set_style(
-        ProgressStyle::with_template(
+        ProgressStyle::with_template(template(
             "{msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes}",
-        )
+            "{msg} [{elapsed_precise}] [{bar:40}] {bytes}/{total_bytes}",
+        ))
+        .unwrap()
+        .progress_chars("#>-"),
+    );
+    Progress::new(pb, task)
+}
+
+pub fn count_progress_bar(stage: Stage, message: String, count: u64) -> Progress {
+    let task = Task::start(stage, Some("files"), &message, count);
+    let pb = ProgressBar::new(count);
+    pb.set_message(message);
+    pb.set_style(
+        ProgressStyle::with_template(template(
+            "{msg} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len}",
+            "{msg} [{elapsed_precise}] [{bar:40}] {pos}/{len}",
+        ))
         .unwrap()
         .progress_chars("#>-"),
     );
-    pb
+    Progress::new(pb, task)
 }
 
-pub fn spinner(message: String) -> ProgressBar {
+pub fn spinner(stage: Stage, message: String) -> Progress {
+    let task = Task::start(stage, None, &message, 0);
     let s = ProgressBar::new(0);
     s.set_message(message);
     s.set_style(
-        ProgressStyle::with_template("{spinner:.blue} {msg}")
+        ProgressStyle::with_template(template("{spinner:.blue} {msg}", "{spinner} {msg}"))
             .unwrap()
             .tick_strings(SPINNER),
     );
-    s.enable_steady_tick(Duration::from_millis(125));
-    s
+    if *DISPLAY == Display::Interactive {
+        s.enable_steady_tick(Duration::from_millis(125));
+    }
+    Progress::new(s, task)
 }